@@ -0,0 +1,34 @@
+use anime_database_lib::episode::{self, Episode};
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+
+const FILENAMES: &[&str] = &[
+    "[sam] Vinland Saga - 24 [BD 1080p FLAC] [6696F95B].mkv",
+    "Girls.und.Panzer.S01E04.1080p-Hi10p.BluRay.FLAC2.1.x264-CTR.[1123C40D].mkv",
+    "[Datte13] Yuyushiki - S01E12 - Uneventful Good Life.mkv",
+    "[Arid] Sound! Euphonium - Creditless OP [D04F5D1D].mkv",
+    "[Group] Show 2 - 05 [1080p].mkv",
+    "Show_ep_11_720p.mp4",
+    "Daily Show 2023-04-15.mkv",
+    "20230101_1830.ts",
+    "Movie.mkv",
+];
+
+fn parse(c: &mut Criterion) {
+    c.bench_function("from_str", |b| {
+        b.iter(|| {
+            for filename in FILENAMES {
+                black_box(black_box(filename).parse::<Episode>().ok());
+            }
+        })
+    });
+    c.bench_function("debug_parse", |b| {
+        b.iter(|| {
+            for filename in FILENAMES {
+                black_box(episode::debug_parse(black_box(filename)));
+            }
+        })
+    });
+}
+
+criterion_group!(benches, parse);
+criterion_main!(benches);
@@ -1,12 +1,23 @@
-use crate::episode::Episode;
-use flexbuffers::DeserializationError;
+use crate::episode::{self, Episode, EpisodeParser, ParseRule, ParseTrace};
+use flexbuffers::{DeserializationError, SerializationError};
 use std::collections::btree_map::Entry;
 use std::fs::{metadata, read_dir, File};
 use std::io::{Read, Write};
-use std::{collections::BTreeMap, path::Path, time::SystemTime};
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, BTreeSet},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard,
+    },
+    time::{Duration, SystemTime},
+};
 
+use regex::Regex;
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
+use unicode_normalization::UnicodeNormalization;
 use walkdir::WalkDir;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -14,17 +25,405 @@ pub struct Anime {
     path: String,
     last_watched: u64,
     last_updated: u64,
+    /// When the anime was first scanned. Zero for older databases.
+    #[serde(default)]
+    date_added: u64,
     current_episode: Episode,
     episodes: EpisodeMap,
+    #[serde(default)]
+    status: WatchStatus,
+    /// Episodes marked as watched, which need not be watched in order.
+    #[serde(default)]
+    watched: BTreeSet<Episode>,
+    /// When each episode was last watched with `.update_watched`.
+    #[serde(default, with = "episode_keyed")]
+    watched_at: BTreeMap<Episode, u64>,
+    /// Length of each episode in seconds, where known.
+    #[serde(default, with = "episode_keyed")]
+    durations: BTreeMap<Episode, u64>,
+    /// Score out of 10, if rated.
+    #[serde(default)]
+    score: Option<u8>,
+    /// Episode numbers count across seasons, as in `Season 2/Show - 26.mkv`,
+    /// and are converted to per-season numbers when scanning.
+    #[serde(default)]
+    absolute_numbering: bool,
+    /// `.next_episode` continues into the specials, in sorted order, after
+    /// the last numbered episode.
+    #[serde(default)]
+    specials_after_finale: bool,
+    /// Added to episode numbers when displayed, e.g. 1 for a release that
+    /// numbers from `E00`. Episodes are stored with their parsed numbers.
+    #[serde(default)]
+    episode_offset: i32,
+    /// Frontend-specific data, such as tracker IDs or notes.
+    #[serde(default)]
+    extra: BTreeMap<String, String>,
+    /// Specials placed after a given episode by `.anchor_special`, by filename.
+    #[serde(default)]
+    anchors: BTreeMap<String, Episode>,
+    /// Episodes released as multipart RAR archives, which need extracting
+    /// before they can be played. Paths list the volumes of each release in
+    /// order, starting with its first volume.
+    #[serde(default)]
+    archived: EpisodeMap,
+    /// Video files from the last scan without a recognizable episode number,
+    /// which are indexed as specials.
+    #[serde(skip)]
+    unparsed: Vec<PathBuf>,
+    /// Problems from the last scan that may explain missing episodes.
+    #[serde(skip)]
+    warnings: Vec<ScanWarning>,
+    #[serde(skip)]
+    clock: Clock,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Default, Serialize, Deserialize)]
 pub struct Database {
     anime_map: BTreeMap<String, Anime>,
+    #[serde(skip)]
+    config: ScanConfig,
+    #[serde(skip)]
+    on_watch: Option<OnWatch>,
+    /// File the database was opened from, used by `.save`.
+    #[serde(skip)]
+    path: Option<PathBuf>,
+    /// Anime directories that could not be read during the last `.update`.
+    #[serde(skip)]
+    unreadable: Vec<(PathBuf, String)>,
+    #[serde(skip)]
+    clock: Clock,
+}
+
+/// Callback run by `Database::update_watched` with the anime name and episode.
+///
+/// Must be `Send` so a `Database` can be shared with `SharedDatabase`.
+pub type WatchCallback = Box<dyn FnMut(&str, &Episode) + Send>;
+
+struct OnWatch(Mutex<WatchCallback>);
+
+impl std::fmt::Debug for OnWatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("OnWatch")
+    }
+}
+
+/// Source of the Unix timestamps recorded as `last_watched` and
+/// `last_updated`. Defaults to the system clock.
+#[derive(Clone)]
+pub struct Clock(Arc<dyn Fn() -> u64 + Send + Sync>);
+
+impl Clock {
+    pub fn new(now: impl Fn() -> u64 + Send + Sync + 'static) -> Self {
+        Self(Arc::new(now))
+    }
+
+    /// Wall-clock time, which can jump backward, e.g. after an NTP sync.
+    pub fn system() -> Self {
+        Self::new(get_time)
+    }
+
+    /// Wraps the clock so each time is at least a second later than the one
+    /// before, so `last_watched` keeps watch order even if the clock jumps
+    /// backward or two watches fall in the same second.
+    pub fn monotonic(self) -> Self {
+        let latest = Arc::new(AtomicU64::new(0));
+        Self::new(move || {
+            let now = self.now();
+            let previous = latest
+                .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |latest| {
+                    Some(now.max(latest + 1))
+                })
+                .expect("Update always succeeds");
+            now.max(previous + 1)
+        })
+    }
+
+    pub fn now(&self) -> u64 {
+        (self.0)()
+    }
+}
+
+impl Default for Clock {
+    fn default() -> Self {
+        Self::system()
+    }
+}
+
+impl std::fmt::Debug for Clock {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Clock")
+    }
+}
+
+/// Episode lists from earlier scans, keyed by anime directory and its
+/// modification time, so `Anime::update_episodes_cached` can skip walking
+/// unchanged directories across restarts.
+///
+/// Stored separately from the database. Entries do not record the
+/// `ScanConfig` they were scanned with, so `.clear` the cache after changing it.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ScanCache {
+    entries: BTreeMap<String, CachedScan>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CachedScan {
+    modified: u64,
+    episodes: EpisodeMap,
+    archived: EpisodeMap,
+}
+
+impl ScanCache {
+    /// Reads a cache written by `.write`, or starts an empty cache if `path`
+    /// does not exist.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        match File::open(path) {
+            Ok(mut f) => {
+                let mut slice = vec![];
+                f.read_to_end(&mut slice)?;
+                Ok(flexbuffers::from_slice::<Self>(&slice)?)
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn write(&self, path: impl AsRef<Path>) -> Result<()> {
+        let mut s = flexbuffers::FlexbufferSerializer::new();
+        self.serialize(&mut s)?;
+        File::create(path)?.write_all(s.view())?;
+        Ok(())
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+/// Options for how `Database::update` scans anime directories.
+#[derive(Debug, Default, Clone)]
+pub struct ScanConfig {
+    /// Treat video files directly inside an anime directory as single-episode
+    /// anime, named after the file without its extension.
+    pub loose_files: bool,
+    /// Names of directories, such as `Extras` or `NCs`, whose files are
+    /// indexed as specials regardless of their filenames. Compared ignoring
+    /// case.
+    pub extras_dirs: Vec<String>,
+    /// Key anime by their folder name in Unicode NFC and lowercase, so folders
+    /// differing only in case or accent encoding are one anime. Lookups are
+    /// normalized the same way.
+    ///
+    /// Keys are stored normalized, so keep this set for databases scanned
+    /// with it.
+    pub normalize_keys: bool,
+    /// Classification hints by lowercase file extension, without the dot,
+    /// for libraries that keep specials in a different container than
+    /// episodes.
+    pub extension_hints: BTreeMap<String, ExtensionHint>,
+    /// Skip anime that look like they are still downloading: modified within
+    /// this many seconds, or containing `.part` files. Skipped anime keep
+    /// their previous episodes until a later `Database::update`.
+    ///
+    /// Only new anime and anime modified since their last scan are checked.
+    pub settle_secs: Option<u64>,
+    /// Directories with fewer video files than this, such as a lone trailer,
+    /// are not added as anime. Anime already in the database are kept.
+    ///
+    /// Zero, the default, also adds directories without any videos.
+    pub min_episodes: usize,
+}
+
+/// Classification of files with an extension in `ScanConfig::extension_hints`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExtensionHint {
+    /// Index files as specials even if they have an episode number.
+    Special,
+    /// Index files matched by the special rule as numbered episodes if they
+    /// have an episode number, e.g. `Show - ED 05.mkv`.
+    Episode,
+}
+
+impl ScanConfig {
+    /// Applies `.extension_hints` to the parse of `filename`.
+    fn hinted(&self, filename: &str, trace: ParseTrace) -> Option<Episode> {
+        let hint = Path::new(filename).extension().and_then(|ext| {
+            self.extension_hints
+                .get(&ext.to_string_lossy().to_lowercase())
+        });
+        match (hint, trace.rule) {
+            (Some(ExtensionHint::Special), _) => Some(Episode::Special {
+                filename: filename.to_owned(),
+            }),
+            (Some(ExtensionHint::Episode), ParseRule::Special) => {
+                let number = |capture: Option<String>| capture?.parse().ok();
+                match (number(trace.season), number(trace.episode)) {
+                    (season, Some(episode)) => Some(Episode::Numbered {
+                        season: season.unwrap_or(1),
+                        episode,
+                    }),
+                    (_, None) => Some(trace.result),
+                }
+            }
+            _ => Some(trace.result),
+        }
+    }
+
+    /// Checks whether the anime at `path` is excluded by `.settle_secs` at
+    /// Unix time `now`.
+    fn in_progress(&self, path: &Path, now: u64) -> bool {
+        let Some(settle_secs) = self.settle_secs else {
+            return false;
+        };
+        let recent = dir_modified_time(path)
+            .is_ok_and(|modified| now < modified.saturating_add(settle_secs));
+        recent
+            || WalkDir::new(path)
+                .max_depth(5)
+                .into_iter()
+                .filter_map(|entry| entry.ok())
+                .any(|entry| entry.path().extension().is_some_and(|ext| ext == "part"))
+    }
+
+    /// Gets the `anime_map` key for an anime named `name`.
+    fn key<'a>(&self, name: &'a str) -> Cow<'a, str> {
+        match self.normalize_keys {
+            true => Cow::Owned(name.nfc().collect::<String>().to_lowercase()),
+            false => Cow::Borrowed(name),
+        }
+    }
 }
 
 pub type EpisodeMap = Vec<(Episode, Vec<String>)>;
 
+/// How `Anime::update_watched` changed progress, compared with the episode
+/// watched before it in episode order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchOutcome {
+    /// The episode is later than the previous one, or the first watched.
+    Advanced,
+    /// The episode was already the current episode, so nothing changed but
+    /// the watch time.
+    SameEpisode,
+    /// The episode is earlier than the previous one, e.g. a rewatch.
+    WentBackward,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum WatchStatus {
+    #[default]
+    PlanToWatch,
+    Watching,
+    OnHold,
+    Dropped,
+    Completed,
+}
+
+impl std::fmt::Display for WatchStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::PlanToWatch => "Plan to Watch",
+            Self::Watching => "Watching",
+            Self::OnHold => "On Hold",
+            Self::Dropped => "Dropped",
+            Self::Completed => "Completed",
+        })
+    }
+}
+
+/// Entry skipped while scanning an anime directory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScanWarning {
+    /// File or directory could not be read, e.g. due to permissions.
+    Unreadable { path: PathBuf, error: String },
+    /// Entry has a video extension but is not a regular file.
+    NotAFile { path: PathBuf },
+    /// The last watched episode is gone, so progress was moved back to the
+    /// nearest earlier episode, or reset if there is none.
+    CurrentEpisodeMissing {
+        episode: Episode,
+        moved_to: Option<Episode>,
+    },
+}
+
+/// Problems found while scanning, collected by `Database::scan_errors`.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ScanErrors {
+    /// Directories that could not be read, with the error.
+    pub unreadable_dirs: Vec<(PathBuf, String)>,
+    /// Video files that could not be read or are not regular files.
+    pub unreadable_files: Vec<(PathBuf, String)>,
+    /// Video files without a recognizable episode number.
+    pub unparsed: Vec<PathBuf>,
+    /// Anime without any episodes.
+    pub empty: Vec<String>,
+    /// Anime whose last watched episode is gone, with that episode and where
+    /// progress moved, as in `ScanWarning::CurrentEpisodeMissing`.
+    pub moved_progress: Vec<(String, Episode, Option<Episode>)>,
+}
+
+/// Result of importing progress from another tracker.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ImportReport {
+    /// Names of the anime whose progress was updated.
+    pub matched: Vec<String>,
+    /// Titles in the export that matched no anime, or more than one.
+    pub unmatched: Vec<String>,
+}
+
+/// Episode files a rescan would add or remove, from `Anime::plan_update`.
+/// Paths are relative to the anime's path.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct EpisodeMapDiff {
+    pub added: Vec<(Episode, String)>,
+    pub removed: Vec<(Episode, String)>,
+}
+
+impl EpisodeMapDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty()
+    }
+}
+
+/// Summary of the anime and episode files found by `Database::scan_report`.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ScanReport {
+    pub anime: usize,
+    pub episodes: usize,
+    /// Video files without a recognizable episode number.
+    pub unparsed: usize,
+    /// Episodes with more than one file.
+    pub duplicates: usize,
+    /// Anime whose directory had no recognizable episodes.
+    pub empty: Vec<String>,
+}
+
+/// Structural problem found by `Database::validate`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ValidationIssue {
+    /// Episode file no longer exists on disk.
+    MissingPath {
+        anime: String,
+        episode: Episode,
+        path: String,
+    },
+    /// Current episode does not exist in the anime's episode map.
+    InvalidCurrentEpisode { anime: String, episode: Episode },
+    /// Anime has no episodes.
+    NoEpisodes { anime: String },
+    /// Same file belongs to more than one anime.
+    DuplicatePath { path: String, anime: Vec<String> },
+}
+
 #[derive(Debug, Error)]
 pub enum InvalidEpisodeError {
     #[error("{episode} Does not exist in \"{anime}\"")]
@@ -34,37 +433,297 @@ pub enum InvalidEpisodeError {
 #[derive(Debug, Error)]
 pub enum DatabaseError {
     #[error("{0}")]
-    IO(std::io::Error),
+    IO(#[from] std::io::Error),
+    #[error("{0}")]
+    Deserialization(#[from] DeserializationError),
+    #[error("{0}")]
+    Serialization(#[from] SerializationError),
     #[error("{0}")]
-    Deserialization(DeserializationError),
+    Json(#[from] serde_json::Error),
     #[error("Invalid path to episode")]
     InvalidFile,
     #[error("Unable to convert file to UTF-8 string")]
     UTF8,
     #[error("{0}")]
     InvalidEpisode(InvalidEpisodeError),
+    #[error("Database was not opened from a file")]
+    NoPath,
+    #[error("Invalid import file")]
+    InvalidImport,
+    #[cfg(feature = "glob")]
+    #[error("Invalid directory pattern: {0}")]
+    Pattern(#[from] glob::PatternError),
+    #[cfg(feature = "reqwest")]
+    #[error("{0}")]
+    Http(#[from] reqwest::Error),
+    #[cfg(feature = "reqwest")]
+    #[error("No poster found for AniList ID {0}")]
+    NoPoster(u64),
 }
 
+/// Length assumed for episodes without a known duration, typical of a TV
+/// episode.
+pub const DEFAULT_EPISODE_LENGTH: Duration = Duration::from_secs(24 * 60);
+
 type Err = DatabaseError;
 
-impl From<std::io::Error> for Err {
-    fn from(v: std::io::Error) -> Self {
-        Self::IO(v)
+type Result<T> = std::result::Result<T, Err>;
+
+/// Converts an `OsStr` or `Path` to a `String`.
+///
+/// Names that are not valid UTF-8 are converted lossily, with `U+FFFD` in
+/// place of invalid bytes, so such files are still indexed. Their stored paths
+/// cannot be opened as is.
+macro_rules! o_to_str {
+    ($x: expr) => {
+        $x.to_string_lossy().into_owned()
+    };
+}
+
+fn is_video(path: &Path) -> bool {
+    path.extension()
+        .map(|e| matches!(e.to_str(), Some("mkv") | Some("mp4") | Some("ts")))
+        .unwrap_or(false)
+}
+
+/// Sorts the paths of one episode so the highest `episode::quality_rank` comes
+/// first, then by path, independent of the order the directory was walked in.
+fn sort_paths(paths: &mut [String]) {
+    paths.sort_by_cached_key(|path| {
+        let rank = Path::new(path)
+            .file_name()
+            .and_then(|filename| filename.to_str())
+            .map_or(0, episode::quality_rank);
+        (std::cmp::Reverse(rank), path.clone())
+    });
+}
+
+/// Checks for a volume of a multipart RAR archive: `.rar`, `.r00`, `.r01`...
+/// or `.part1.rar`, `.part2.rar`...
+fn is_archive_volume(path: &Path) -> bool {
+    let Some(ext) = path.extension().and_then(|e| e.to_str()) else {
+        return false;
+    };
+    let ext = ext.to_ascii_lowercase();
+    ext == "rar"
+        || (ext.len() == 3 && ext.starts_with('r') && ext[1..].bytes().all(|b| b.is_ascii_digit()))
+}
+
+/// Moves episodes without a season marker into the season of their directory.
+///
+/// A directory's season comes from its files if every marked file agrees,
+/// otherwise from its name (`Season 2`). Paths are relative to `root`, whose
+/// own name is used for files directly inside it.
+fn inherit_seasons(found: &mut [(Episode, String)], root: &Path) {
+    let filename = |path: &str| o_to_str!(Path::new(path).file_name().unwrap_or_default());
+    let mut dir_seasons: BTreeMap<PathBuf, BTreeSet<u32>> = BTreeMap::new();
+    for (_, path) in found.iter() {
+        let dir = Path::new(path)
+            .parent()
+            .unwrap_or(Path::new(""))
+            .to_path_buf();
+        let seasons = dir_seasons.entry(dir).or_default();
+        seasons.extend(episode::explicit_season(&filename(path)));
+    }
+
+    for (ep, path) in found.iter_mut() {
+        let Episode::Numbered { season, .. } = ep else {
+            continue;
+        };
+        if episode::explicit_season(&filename(path)).is_some() {
+            continue;
+        }
+        let dir = Path::new(path.as_str()).parent().unwrap_or(Path::new(""));
+        let inherited = match &dir_seasons[dir] {
+            seasons if seasons.len() == 1 => seasons.first().copied(),
+            _ => root
+                .join(dir)
+                .file_name()
+                .and_then(|name| episode::season_from_dir(name.to_str()?)),
+        };
+        if let Some(inherited) = inherited {
+            *season = inherited;
+        }
+    }
+}
+
+/// Converts absolute episode numbers into per-season numbers, continuing from
+/// the highest number of the earlier seasons, so `Season 2/Show - 26.mkv`
+/// after 25 episodes becomes S02E01.
+///
+/// Files with an explicit season marker (`S02E01`) are already per-season and
+/// left as is. Season 0 is not renumbered.
+fn absolute_to_seasons(found: &mut [(Episode, String)]) {
+    let filename = |path: &str| o_to_str!(Path::new(path).file_name().unwrap_or_default());
+    let absolute = |path: &str| episode::explicit_season(&filename(path)).is_none();
+    let mut last_of_season: BTreeMap<u32, u32> = BTreeMap::new();
+    for (ep, path) in found.iter() {
+        if let Episode::Numbered { season, episode } = ep {
+            if *season > 0 && absolute(path) {
+                let last = last_of_season.entry(*season).or_default();
+                *last = (*last).max(*episode);
+            }
+        }
+    }
+    for (ep, path) in found.iter_mut() {
+        let Episode::Numbered { season, episode } = ep else {
+            continue;
+        };
+        if *season == 0 || !absolute(path) {
+            continue;
+        }
+        let offset = last_of_season
+            .range(1..*season)
+            .map(|(_, last)| *last)
+            .max()
+            .unwrap_or(0);
+        if *episode > offset {
+            *episode -= offset;
+        }
+    }
+}
+
+/// Splits the path of an archive volume into the path of its set, without
+/// volume suffixes, and its position in the set.
+fn archive_volume(relative: &Path) -> (PathBuf, u32) {
+    let ext = relative
+        .extension()
+        .map_or(String::new(), |ext| o_to_str!(ext).to_ascii_lowercase());
+    let stem = relative.with_extension("");
+    if ext != "rar" {
+        // `.rar` is the first volume, followed by `.r00`, `.r01`...
+        let volume = ext.get(1..).and_then(|n| n.parse::<u32>().ok());
+        return (stem, volume.map_or(u32::MAX, |n| n + 1));
+    }
+    // RAR5 names every volume `.rar`, numbered `.part1`, `.part2`...
+    let part = stem.extension().and_then(|part| {
+        let part = o_to_str!(part).to_ascii_lowercase();
+        part.strip_prefix("part")?.parse().ok()
+    });
+    match part {
+        Some(part) => (stem.with_extension(""), part),
+        None => (stem, 0),
     }
 }
 
-impl From<DeserializationError> for Err {
-    fn from(v: DeserializationError) -> Self {
-        Self::Deserialization(v)
+/// Groups archive volumes into sets by their path without volume suffixes,
+/// parsing each as if it were a single `.rar`. Sets of the same episode, such
+/// as two releases, are merged as `Anime::update_episodes` merges files.
+fn group_archives(volumes: &[PathBuf], root: &Path) -> EpisodeMap {
+    let mut sets: BTreeMap<PathBuf, Vec<(u32, String)>> = BTreeMap::new();
+    for path in volumes {
+        let Ok(relative) = path.strip_prefix(root) else {
+            continue;
+        };
+        let (set, position) = archive_volume(relative);
+        sets.entry(set)
+            .or_default()
+            .push((position, o_to_str!(relative)));
     }
+    let mut archived: EpisodeMap = Vec::new();
+    for (set, mut volumes) in sets {
+        let Some(name) = set.file_name() else {
+            continue;
+        };
+        let Ok(ep) = format!("{}.rar", o_to_str!(name)).parse::<Episode>() else {
+            continue;
+        };
+        volumes.sort();
+        let paths = volumes.into_iter().map(|(_, path)| path);
+        match archived.iter_mut().find(|(v, _)| ep.normalized_eq(v)) {
+            Some((_, existing)) => existing.extend(paths),
+            None => archived.push((ep, paths.collect())),
+        }
+    }
+    archived.sort_by(|(a, _), (b, _)| a.cmp(b));
+    archived
 }
 
-type Result<T> = std::result::Result<T, Err>;
+lazy_static::lazy_static! {
+    static ref REG_XML_ANIME: Regex = Regex::new(r#"(?s)<anime>(.*?)</anime>"#).unwrap();
+    static ref REG_XML_FIELD: Regex = Regex::new(r#"(?s)<(?P<tag>[a-z_]+)>(?:<!\[CDATA\[(?P<cdata>.*?)\]\]>|(?P<text>[^<]*))</[a-z_]+>"#).unwrap();
+}
 
-macro_rules! o_to_str {
-    ($x: expr) => {
-        $x.to_str().unwrap().to_string()
+/// Entry of a MyAnimeList XML export.
+#[derive(Debug, Default)]
+struct MalEntry {
+    title: String,
+    watched_episodes: usize,
+    score: u8,
+    status: Option<WatchStatus>,
+}
+
+/// Reads the entries of a MyAnimeList XML export.
+///
+/// The export is a flat list of simple elements, so it is read with regexes
+/// rather than a full XML parser.
+fn parse_mal_xml(xml: &str) -> Result<Vec<MalEntry>> {
+    if !xml.contains("<myanimelist>") {
+        return Err(Err::InvalidImport);
+    }
+    let unescape = |s: &str| {
+        s.replace("&lt;", "<")
+            .replace("&gt;", ">")
+            .replace("&quot;", "\"")
+            .replace("&apos;", "'")
+            .replace("&amp;", "&")
     };
+    REG_XML_ANIME
+        .captures_iter(xml)
+        .map(|anime| {
+            let mut entry = MalEntry::default();
+            for field in REG_XML_FIELD.captures_iter(&anime[1]) {
+                let value = match (field.name("cdata"), field.name("text")) {
+                    (Some(cdata), _) => cdata.as_str().to_owned(),
+                    (_, Some(text)) => unescape(text.as_str()),
+                    _ => String::new(),
+                };
+                let value = value.trim();
+                match &field["tag"] {
+                    "series_title" => entry.title = value.to_owned(),
+                    "my_watched_episodes" => {
+                        entry.watched_episodes = value.parse().map_err(|_| Err::InvalidImport)?
+                    }
+                    "my_score" => entry.score = value.parse().map_err(|_| Err::InvalidImport)?,
+                    "my_status" => {
+                        entry.status = match value {
+                            "Watching" => Some(WatchStatus::Watching),
+                            "Completed" => Some(WatchStatus::Completed),
+                            "On-Hold" => Some(WatchStatus::OnHold),
+                            "Dropped" => Some(WatchStatus::Dropped),
+                            "Plan to Watch" => Some(WatchStatus::PlanToWatch),
+                            _ => None,
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            Ok(entry)
+        })
+        .collect()
+}
+
+/// Serializes a map keyed by `Episode` as a list of pairs, since flexbuffers
+/// and JSON only allow string keys.
+mod episode_keyed {
+    use super::Episode;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use std::collections::BTreeMap;
+
+    pub fn serialize<S: Serializer, V: Serialize>(
+        map: &BTreeMap<Episode, V>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(map)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>, V: Deserialize<'de>>(
+        deserializer: D,
+    ) -> Result<BTreeMap<Episode, V>, D::Error> {
+        Ok(Vec::<(Episode, V)>::deserialize(deserializer)?
+            .into_iter()
+            .collect())
+    }
 }
 
 fn get_time() -> u64 {
@@ -74,57 +733,419 @@ fn get_time() -> u64 {
         .as_secs()
 }
 
+/// Short summary such as `Show: S01E05 / 24 (Watching)`, unlike the verbose
+/// `Debug` which includes every episode path.
+impl std::fmt::Display for Anime {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = Path::new(&self.path)
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or(&self.path);
+        write!(f, "{name}: ")?;
+        match self.last_finished().map(|ep| self.display_episode(&ep)) {
+            Some(Episode::Numbered { season, episode }) => write!(f, "S{season:02}E{episode:02}")?,
+            Some(ep) => write!(f, "{ep}")?,
+            None => f.write_str("-")?,
+        }
+        write!(f, " / {} ({})", self.episode_count(), self.status)
+    }
+}
+
 impl Anime {
     pub fn from_path(path: impl AsRef<Path>, time: u64) -> Self {
-        let path = path.as_ref();
-        let mut anime = Anime {
-            path: o_to_str!(path),
-            last_watched: 0,
-            last_updated: time,
-            current_episode: Episode::from((1, 1)),
-            episodes: Vec::new(),
-        };
+        let mut anime = Self::with_episodes(path, Vec::new());
+        anime.last_updated = time;
+        anime.date_added = time;
         anime.update_episodes();
         anime
     }
 
+    /// Creates an anime from a known episode map without scanning `path`.
+    pub fn with_episodes(path: impl AsRef<Path>, mut episodes: EpisodeMap) -> Self {
+        episodes.sort_by(|(a, _), (b, _)| a.cmp(b));
+        let clock = Clock::default();
+        Anime {
+            path: o_to_str!(path.as_ref()),
+            last_watched: 0,
+            last_updated: clock.now(),
+            date_added: clock.now(),
+            current_episode: Episode::from((1, 1)),
+            episodes,
+            status: WatchStatus::default(),
+            watched: BTreeSet::new(),
+            watched_at: BTreeMap::new(),
+            durations: BTreeMap::new(),
+            score: None,
+            absolute_numbering: false,
+            specials_after_finale: false,
+            episode_offset: 0,
+            extra: BTreeMap::new(),
+            anchors: BTreeMap::new(),
+            archived: Vec::new(),
+            unparsed: Vec::new(),
+            warnings: Vec::new(),
+            clock,
+        }
+    }
+
+    /// Rescans the anime directory, storing episode paths relative to it.
+    ///
+    /// If the anime path is a single video file, it is the only episode.
+    /// Multiple paths of one episode are sorted highest `episode::quality_rank`
+    /// first, then by path.
     pub fn update_episodes(&mut self) {
-        WalkDir::new(&self.path)
+        self.update_episodes_with(&ScanConfig::default())
+    }
+
+    /// Same as `.update_episodes_with`, reusing the episodes in `cache` if the
+    /// anime directory has not been modified since it was cached.
+    ///
+    /// As with `Database::update`, only the anime directory's own modification
+    /// time is checked. `.unparsed` and `.scan_warnings` are empty after a
+    /// cached scan.
+    pub fn update_episodes_cached(&mut self, config: &ScanConfig, cache: &mut ScanCache) {
+        let modified = metadata(&self.path)
+            .and_then(|m| m.modified())
+            .ok()
+            .and_then(|time| time.duration_since(SystemTime::UNIX_EPOCH).ok())
+            .map(|time| time.as_secs());
+        match (cache.entries.get(&self.path), modified) {
+            (Some(cached), Some(modified)) if cached.modified == modified => {
+                self.episodes = cached.episodes.clone();
+                self.archived = cached.archived.clone();
+                self.unparsed.clear();
+                self.warnings.clear();
+                self.fix_current_episode();
+            }
+            _ => {
+                self.update_episodes_with(config);
+                if let Some(modified) = modified {
+                    let cached = CachedScan {
+                        modified,
+                        episodes: self.episodes.clone(),
+                        archived: self.archived.clone(),
+                    };
+                    cache.entries.insert(self.path.clone(), cached);
+                }
+            }
+        }
+    }
+
+    /// Previews `.update_episodes` without applying it, listing the episode
+    /// files it would add and remove. A file parsed differently than before
+    /// is both removed and added.
+    pub fn plan_update(&self) -> EpisodeMapDiff {
+        self.plan_update_with(&ScanConfig::default())
+    }
+
+    /// Same as `.plan_update`, previewing `.update_episodes_with(config)`.
+    pub fn plan_update_with(&self, config: &ScanConfig) -> EpisodeMapDiff {
+        let flatten = |episodes: &EpisodeMap| -> BTreeSet<(Episode, String)> {
+            episodes
+                .iter()
+                .flat_map(|(ep, paths)| paths.iter().map(move |path| (ep.clone(), path.clone())))
+                .collect()
+        };
+        let mut planned = self.clone();
+        planned.update_episodes_with(config);
+        let (before, after) = (flatten(&self.episodes), flatten(&planned.episodes));
+        EpisodeMapDiff {
+            added: after.difference(&before).cloned().collect(),
+            removed: before.difference(&after).cloned().collect(),
+        }
+    }
+
+    /// Same as `.update_episodes`, applying `config.extras_dirs`.
+    pub fn update_episodes_with(&mut self, config: &ScanConfig) {
+        let in_extras = |relative: &Path| {
+            relative.parent().is_some_and(|parent| {
+                parent.components().any(|dir| {
+                    let dir = dir.as_os_str().to_string_lossy();
+                    config
+                        .extras_dirs
+                        .iter()
+                        .any(|extras| extras.eq_ignore_ascii_case(&dir))
+                })
+            })
+        };
+        self.episodes.clear();
+        self.archived.clear();
+        self.unparsed.clear();
+        self.warnings.clear();
+        let root = PathBuf::from(&self.path);
+        let mut volumes = Vec::new();
+        let mut found = WalkDir::new(&root)
             .max_depth(5)
-            .min_depth(1)
+            .min_depth(if root.is_file() { 0 } else { 1 })
             .into_iter()
-            .filter_map(|d| Some(d.ok()?)) // Report directory not found
-            .filter(|d| {
-                d.file_type().is_file()
-                    && d.path()
-                        .extension()
-                        .map(|e| matches!(e.to_str(), Some("mkv") | Some("mp4") | Some("ts")))
-                        .unwrap_or(false)
+            .filter_map(|d| match d {
+                Ok(d) if d.file_type().is_file() && is_archive_volume(d.path()) => {
+                    volumes.push(d.into_path());
+                    None
+                }
+                Ok(d) if !is_video(d.path()) => None,
+                Ok(d) if d.file_type().is_file() => Some(d),
+                Ok(d) => {
+                    self.warnings.push(ScanWarning::NotAFile {
+                        path: d.into_path(),
+                    });
+                    None
+                }
+                Err(e) => {
+                    self.warnings.push(ScanWarning::Unreadable {
+                        path: e.path().unwrap_or(&root).to_path_buf(),
+                        error: e.to_string(),
+                    });
+                    None
+                }
             })
             .filter_map(|dir_entry| {
-                let episode = Episode::try_from(dir_entry.path()).ok()?;
-                let path = dir_entry.path().to_str()?.to_owned();
-
-                Some((episode, path))
+                let relative = dir_entry.path().strip_prefix(&root).ok()?;
+                let filename = o_to_str!(dir_entry.file_name());
+                if in_extras(relative) {
+                    let special = Episode::Special { filename };
+                    return Some((special, o_to_str!(relative)));
+                }
+                let trace = episode::debug_parse(&filename);
+                let fallback = trace.rule == ParseRule::Fallback;
+                let parsed = config
+                    .hinted(&filename, trace)
+                    .map(|ep| (ep, o_to_str!(relative)));
+                if parsed.is_none() || fallback {
+                    self.unparsed.push(dir_entry.into_path());
+                }
+                parsed
             })
-            .for_each(
-                |(ep, path)| match self.episodes.iter_mut().find(|(v, _)| ep.eq(v)) {
-                    Some((_, paths)) => paths.push(path.clone()),
-                    None => self.episodes.push((ep, vec![path])),
-                },
-            );
+            .collect::<Vec<_>>();
+        inherit_seasons(&mut found, &root);
+        if self.absolute_numbering {
+            absolute_to_seasons(&mut found);
+        }
+        for (ep, path) in found {
+            match self.episodes.iter_mut().find(|(v, _)| ep.normalized_eq(v)) {
+                Some((_, paths)) => paths.push(path),
+                None => self.episodes.push((ep, vec![path])),
+            }
+        }
+        for (_, paths) in &mut self.episodes {
+            sort_paths(paths);
+        }
+        self.episodes.sort_by(|(a, _), (b, _)| a.cmp(b));
+        self.archived = group_archives(&volumes, &root);
+        self.fix_current_episode();
+    }
+
+    /// Moves progress back to the nearest earlier episode if the last watched
+    /// episode no longer exists, e.g. after its season was deleted.
+    fn fix_current_episode(&mut self) {
+        if self.last_watched == 0
+            || self
+                .episodes
+                .iter()
+                .any(|(ep, _)| self.current_episode.eq(ep))
+        {
+            return;
+        }
+        let moved_to = self
+            .episodes
+            .iter()
+            .rev()
+            .map(|(ep, _)| ep)
+            .find(|ep| !matches!(ep, Episode::Special { .. }) && **ep < self.current_episode)
+            .cloned();
+        let episode = match &moved_to {
+            Some(ep) => std::mem::replace(&mut self.current_episode, ep.clone()),
+            None => {
+                self.last_watched = 0;
+                std::mem::replace(&mut self.current_episode, Episode::from((1, 1)))
+            }
+        };
+        self.warnings
+            .push(ScanWarning::CurrentEpisodeMissing { episode, moved_to });
+    }
+
+    /// Copies the watch progress of `other`, leaving the episodes as they are:
+    /// current episode, last watched time, watched episodes with their times,
+    /// status and score.
+    ///
+    /// If the copied current episode is not one of these episodes, progress
+    /// moves back as after a rescan, with a `ScanWarning::CurrentEpisodeMissing`.
+    pub fn clone_progress_from(&mut self, other: &Anime) {
+        self.current_episode = other.current_episode.clone();
+        self.last_watched = other.last_watched;
+        self.watched = other.watched.clone();
+        self.watched_at = other.watched_at.clone();
+        self.status = other.status;
+        self.score = other.score;
+        self.fix_current_episode();
+    }
+
+    /// Restores the invariants of the episode lists after they were edited
+    /// directly, see `Database::rebuild_indexes`.
+    fn rebuild_indexes(&mut self) {
         self.episodes.sort_by(|(a, _), (b, _)| a.cmp(b));
+        self.episodes.dedup_by(|(a, later), (b, paths)| {
+            let duplicate = a == b;
+            if duplicate {
+                paths.append(later);
+            }
+            duplicate
+        });
+        for (_, paths) in &mut self.episodes {
+            sort_paths(paths);
+            paths.dedup();
+        }
+        self.archived.sort_by(|(a, _), (b, _)| a.cmp(b));
+        self.fix_current_episode();
+    }
+
+    /// Merges the episodes and progress of `other`, an entry for the same
+    /// anime, keeping the most recent position.
+    fn merge(&mut self, other: Anime) {
+        for (ep, paths) in other.episodes {
+            match self.episodes.iter_mut().find(|(v, _)| ep.normalized_eq(v)) {
+                Some((_, existing)) => {
+                    for path in paths {
+                        if !existing.contains(&path) {
+                            existing.push(path);
+                        }
+                    }
+                    sort_paths(existing);
+                }
+                None => {
+                    let index = self.episodes.partition_point(|(v, _)| *v < ep);
+                    self.episodes.insert(index, (ep, paths));
+                }
+            }
+        }
+        if other.last_watched > self.last_watched {
+            self.last_watched = other.last_watched;
+            self.current_episode = other.current_episode;
+        }
+        self.date_added = self.date_added.min(other.date_added);
+        if self.archived.is_empty() {
+            self.archived = other.archived;
+        }
+        self.watched.extend(other.watched);
+        for (ep, time) in other.watched_at {
+            let latest = self.watched_at.entry(ep).or_insert(time);
+            *latest = (*latest).max(time);
+        }
+        for (ep, secs) in other.durations {
+            self.durations.entry(ep).or_insert(secs);
+        }
+        self.score = self.score.or(other.score);
+        for (key, value) in other.extra {
+            self.extra.entry(key).or_insert(value);
+        }
+        for (filename, after) in other.anchors {
+            self.anchors.entry(filename).or_insert(after);
+        }
+    }
+
+    /// Adds a single episode file, keeping episodes sorted without sorting
+    /// them again.
+    ///
+    /// `path` is relative to the anime path. It is added to an existing
+    /// episode if one matches with `Episode::normalized_eq`, whose paths are
+    /// then sorted as in `.update_episodes`.
+    pub fn insert_episode(&mut self, ep: Episode, path: String) {
+        if let Some((_, paths)) = self.episodes.iter_mut().find(|(v, _)| ep.normalized_eq(v)) {
+            paths.push(path);
+            sort_paths(paths);
+            return;
+        }
+        let index = self.episodes.partition_point(|(v, _)| *v < ep);
+        self.episodes.insert(index, (ep, vec![path]));
     }
 
     /// Gets current episode of directory in (season, episode) form.
+    ///
+    /// This is the last episode passed to `.update_watched`, or the first
+    /// episode if nothing has been watched. Prefer `.last_finished` and
+    /// `.up_next` when the distinction matters.
     pub fn current_episode(&self) -> Episode {
         self.current_episode.clone()
     }
 
+    /// Gets when the anime was first scanned, as a Unix timestamp.
+    pub fn date_added(&self) -> u64 {
+        self.date_added
+    }
+
+    /// Gets the last episode marked as watched, or `None` if never started.
+    pub fn last_finished(&self) -> Option<Episode> {
+        match self.last_watched {
+            0 => None,
+            _ => Some(self.current_episode.clone()),
+        }
+    }
+
+    /// Gets the episode to watch next.
+    ///
+    /// This is the first numbered episode if never started, otherwise the
+    /// episode after `.last_finished`.
+    pub fn up_next(&self) -> Option<Episode> {
+        match self.last_finished() {
+            Some(_) => self.next_episode().ok().flatten(),
+            None => self.first_episode().cloned(),
+        }
+    }
+
+    /// Gets the episode to resume from.
+    ///
+    /// This is the first numbered episode if never started, otherwise the
+    /// first non-special episode sorted after `.last_finished`. Unlike
+    /// `.up_next`, this does not depend on episode numbers being contiguous.
+    pub fn resume_episode(&self) -> Option<Episode> {
+        match self.last_finished() {
+            Some(last) => self
+                .episodes
+                .iter()
+                .map(|(ep, _)| ep)
+                .skip_while(|ep| last.ne(ep))
+                .skip(1)
+                .find(|ep| !matches!(ep, Episode::Special { .. }))
+                .cloned(),
+            None => self.first_episode().cloned(),
+        }
+    }
+
+    /// Gets the first numbered episode, or the first episode if there is none.
+    fn first_episode(&self) -> Option<&Episode> {
+        self.episodes
+            .iter()
+            .map(|(ep, _)| ep)
+            .find(|ep| matches!(ep, Episode::Numbered { .. }))
+            .or_else(|| self.episodes.first().map(|(ep, _)| ep))
+    }
+
     pub fn next_episode<'a>(&self) -> Result<Option<Episode>> {
+        let next_special = |after: Option<&Episode>| {
+            self.episodes
+                .iter()
+                .map(|(ep, _)| ep)
+                .filter(|ep| matches!(ep, Episode::Special { .. }))
+                .find(|ep| match after {
+                    Some(after) => *ep > after,
+                    None => true,
+                })
+                .filter(|_| self.specials_after_finale)
+                .cloned()
+        };
         match self.current_episode {
-            Episode::Numbered { season, episode } => Ok(self.next_episode_raw((season, episode))),
-            Episode::Special { .. } => Ok(None),
+            Episode::Numbered { season, episode } => Ok(self
+                .next_episode_raw((season, episode))
+                .or_else(|| next_special(None))),
+            Episode::Special { .. } => Ok(next_special(Some(&self.current_episode))),
+            Episode::Dated { .. } | Episode::Captured { .. } => Ok(self
+                .episodes
+                .iter()
+                .map(|(ep, _)| ep)
+                .skip_while(|ep| self.current_episode.ne(ep))
+                .nth(1)
+                .filter(|ep| ep.air_date().is_some())
+                .cloned()),
         }
     }
 
@@ -139,63 +1160,682 @@ impl Anime {
                 .map(|v| v.0.clone())
         };
 
+        // Episodes are sorted, so this is the first episode of the next
+        // season even if it continues the previous season's numbering.
+        let first_of_season = |season| {
+            self.episodes
+                .iter()
+                .map(|(ep, _)| ep)
+                .find(|ep| matches!(ep, Episode::Numbered { season: s, .. } if *s == season))
+                .cloned()
+        };
+
         if let Some(episode) = get_episode(season, episode + 1) {
             Some(episode)
-        } else if let Some(episode) = get_episode(season + 1, 0) {
-            Some(episode)
-        } else if let Some(episode) = get_episode(season + 1, 1) {
-            Some(episode)
         } else {
-            None
+            first_of_season(season + 1)
         }
     }
 
-    pub fn episodes(&self) -> &EpisodeMap {
-        &self.episodes
+    /// Places the special `filename` directly after `after` in `.playlist`
+    /// and `.seasons`, e.g. an OVA set between two seasons.
+    pub fn anchor_special(&mut self, filename: &str, after: Episode) -> Result<()> {
+        let special = Episode::Special {
+            filename: filename.to_owned(),
+        };
+        for ep in [&special, &after] {
+            if !self.episodes.iter().any(|(v, _)| ep.eq(v)) {
+                return Err(self.not_exist(ep.clone()));
+            }
+        }
+        self.anchors.insert(filename.to_owned(), after);
+        Ok(())
     }
 
-    /// Prefer `.update_watched` because it checks if episode exists in episode_map.
-    pub unsafe fn update_watched_unchecked(&mut self, watched: Episode) {
-        let timestamp = get_time();
-        self.last_watched = timestamp;
-        self.current_episode = watched;
+    fn anchor(&self, special: &Episode) -> Option<Episode> {
+        match special {
+            Episode::Special { filename } => self.anchors.get(filename).cloned(),
+            _ => None,
+        }
     }
 
-    pub fn update_watched(&mut self, watched: Episode) -> Result<()> {
-        match self.episodes.iter().find(|(ep, _)| watched.eq(ep)) {
-            Some(_) => Ok(unsafe { self.update_watched_unchecked(watched) }),
-            None => Err(Err::InvalidEpisode(InvalidEpisodeError::NotExist {
-                anime: self.path.to_string(),
-                episode: watched,
-            })),
-        }
+    /// Gets episodes in watch order, with specials anchored by
+    /// `.anchor_special` after their episode and the rest last.
+    pub fn playlist(&self) -> Vec<&Episode> {
+        self.playlist_by(|special| self.anchor(special))
     }
-}
 
-fn dir_modified_time(path: impl AsRef<Path>) -> u64 {
-    metadata(path)
-        .unwrap()
-        .modified()
-        .unwrap()
-        .duration_since(SystemTime::UNIX_EPOCH)
-        .unwrap()
-        .as_secs()
-}
+    /// Gets episodes in watch order, placing each special directly after the
+    /// episode returned by `after`.
+    ///
+    /// Specials for which `after` returns `None`, or an episode that does not
+    /// exist, are placed last.
+    pub fn playlist_by(&self, after: impl Fn(&Episode) -> Option<Episode>) -> Vec<&Episode> {
+        let (specials, episodes): (Vec<_>, Vec<_>) = self
+            .episodes
+            .iter()
+            .map(|(ep, _)| ep)
+            .partition(|ep| matches!(ep, Episode::Special { .. }));
+        let mut specials = specials
+            .into_iter()
+            .map(|special| (after(special), special))
+            .collect::<Vec<_>>();
 
-impl Database {
+        let mut playlist = Vec::with_capacity(self.episodes.len());
+        for ep in episodes {
+            playlist.push(ep);
+            specials.retain(|(anchor, special)| match anchor {
+                Some(anchor) if anchor == ep => {
+                    playlist.push(special);
+                    false
+                }
+                _ => true,
+            });
+        }
+        playlist.extend(specials.into_iter().map(|(_, special)| special));
+        playlist
+    }
+
+    /// Groups numbered episodes by season.
+    ///
+    /// Specials anchored by `.anchor_special` to a numbered episode are
+    /// included after it.
+    pub fn seasons(&self) -> BTreeMap<u32, Vec<&Episode>> {
+        self.playlist()
+            .into_iter()
+            .scan(None, |season, ep| {
+                match ep {
+                    Episode::Numbered { season: s, .. } => *season = Some(*s),
+                    Episode::Special { .. } if self.anchor(ep).is_some() => {}
+                    _ => *season = None,
+                }
+                Some(season.map(|season| (season, ep)))
+            })
+            .flatten()
+            .fold(BTreeMap::new(), |mut seasons, (season, ep)| {
+                seasons.entry(season).or_insert_with(Vec::new).push(ep);
+                seasons
+            })
+    }
+
+    /// Counts the distinct seasons of numbered episodes. Season 0, which
+    /// holds specials, is not counted.
+    pub fn season_count(&self) -> usize {
+        self.episodes
+            .iter()
+            .filter_map(|(ep, _)| match ep {
+                Episode::Numbered { season, .. } if *season > 0 => Some(season),
+                _ => None,
+            })
+            .collect::<BTreeSet<_>>()
+            .len()
+    }
+
+    /// Checks whether numbered episodes span more than one season.
+    pub fn is_multi_season(&self) -> bool {
+        self.season_count() > 1
+    }
+
+    /// Formats `ep` for display, omitting the season (`E05`) when the anime
+    /// only has season 1 besides specials. `.offset` is applied as in
+    /// `.display_episode`.
+    pub fn format_episode(&self, ep: &Episode) -> String {
+        let single_season = self
+            .episodes
+            .iter()
+            .all(|(ep, _)| !matches!(ep, Episode::Numbered { season, .. } if *season > 1));
+        match self.display_episode(ep) {
+            Episode::Numbered { season: 1, episode } if single_season => format!("E{episode:02}"),
+            ep => ep.to_string(),
+        }
+    }
+
+    /// Gets the episodes of `season` with their paths, in sorted order.
+    ///
+    /// Season 0 holds specials, so it also includes `Episode::Special`s.
+    pub fn episodes_in_season(&self, season: usize) -> Vec<(&Episode, &[String])> {
+        self.episodes
+            .iter()
+            .filter(|(ep, _)| match ep {
+                Episode::Numbered { season: s, .. } => *s as usize == season,
+                Episode::Special { .. } => season == 0,
+                _ => false,
+            })
+            .map(|(ep, paths)| (ep, paths.as_slice()))
+            .collect()
+    }
+
+    /// Finds numbered episodes absent from their season, assuming each season
+    /// is numbered from 1 up to its last episode.
+    pub fn missing_episodes(&self) -> Vec<Episode> {
+        self.seasons()
+            .into_iter()
+            .flat_map(|(season, eps)| {
+                let present = eps
+                    .into_iter()
+                    .filter_map(|ep| match ep {
+                        Episode::Numbered { episode, .. } => Some(*episode),
+                        _ => None,
+                    })
+                    .collect::<BTreeSet<_>>();
+                let last = present.last().copied().unwrap_or(0);
+                (1..last)
+                    .filter(move |episode| !present.contains(episode))
+                    .map(move |episode| Episode::Numbered { season, episode })
+            })
+            .collect()
+    }
+
+    pub fn episodes(&self) -> &EpisodeMap {
+        &self.episodes
+    }
+
+    /// Serializes the anime, including its episodes and progress, as a
+    /// standalone JSON document.
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string(self)?)
+    }
+
+    /// Gets numbered episodes whose files look like different shows, hinting
+    /// at a parse error that merged two episodes under one number.
+    ///
+    /// Files are compared by `episode::base_title`, and are suspicious if two
+    /// titles have no word in common.
+    pub fn suspicious_groupings(&self) -> Vec<(&Episode, &[String])> {
+        self.episodes
+            .iter()
+            .filter(|(ep, paths)| {
+                if !matches!(ep, Episode::Numbered { .. }) {
+                    return false;
+                }
+                let titles = paths
+                    .iter()
+                    .filter_map(|path| Path::new(path).file_name())
+                    .map(|filename| episode::base_title(&filename.to_string_lossy()))
+                    .filter(|title| !title.is_empty())
+                    .collect::<Vec<_>>();
+                titles.iter().enumerate().any(|(i, a)| {
+                    titles[i + 1..]
+                        .iter()
+                        .any(|b| !a.split(' ').any(|word| b.split(' ').any(|v| v == word)))
+                })
+            })
+            .map(|(ep, paths)| (ep, paths.as_slice()))
+            .collect()
+    }
+
+    /// Gets episodes only available as multipart RAR archives, with the paths
+    /// of their volumes. These are not in `.episodes` until extracted.
+    pub fn archived_episodes(&self) -> &EpisodeMap {
+        &self.archived
+    }
+
+    /// Counts episode files, including every version of an episode.
+    pub fn total_paths(&self) -> usize {
+        self.episodes.iter().map(|(_, paths)| paths.len()).sum()
+    }
+
+    /// Counts numbered episodes.
+    pub fn episode_count(&self) -> usize {
+        self.episodes
+            .iter()
+            .filter(|(ep, _)| matches!(ep, Episode::Numbered { .. }))
+            .count()
+    }
+
+    /// Counts episodes that are not numbered, such as specials.
+    pub fn special_count(&self) -> usize {
+        self.episodes.len() - self.episode_count()
+    }
+
+    pub fn get_extra(&self, key: &str) -> Option<&str> {
+        self.extra.get(key).map(String::as_str)
+    }
+
+    /// Stores frontend-specific data under `key`, replacing any previous value.
+    pub fn set_extra(&mut self, key: impl Into<String>, value: impl Into<String>) {
+        self.extra.insert(key.into(), value.into());
+    }
+
+    pub fn status(&self) -> WatchStatus {
+        self.status
+    }
+
+    pub fn set_status(&mut self, status: WatchStatus) {
+        self.status = status;
+    }
+
+    pub fn score(&self) -> Option<u8> {
+        self.score
+    }
+
+    pub fn set_score(&mut self, score: Option<u8>) {
+        self.score = score;
+    }
+
+    /// Sets whether `.next_episode` continues into the specials after the
+    /// last numbered episode, rather than returning `None`.
+    pub fn set_specials_after_finale(&mut self, specials_after_finale: bool) {
+        self.specials_after_finale = specials_after_finale;
+    }
+
+    pub fn offset(&self) -> i32 {
+        self.episode_offset
+    }
+
+    /// Sets the correction added to episode numbers by `.display_episode`.
+    pub fn set_offset(&mut self, offset: i32) {
+        self.episode_offset = offset;
+    }
+
+    /// Gets the episode number to show for `ep`, a stored episode, with
+    /// `.offset` applied. Other kinds of episodes are unchanged.
+    pub fn display_episode(&self, ep: &Episode) -> Episode {
+        match ep {
+            Episode::Numbered { season, episode } => Episode::Numbered {
+                season: *season,
+                episode: episode.saturating_add_signed(self.episode_offset),
+            },
+            ep => ep.clone(),
+        }
+    }
+
+    /// Inverse of `.display_episode`, for looking up an episode the user
+    /// chose by its displayed number.
+    pub fn stored_episode(&self, displayed: &Episode) -> Episode {
+        match displayed {
+            Episode::Numbered { season, episode } => Episode::Numbered {
+                season: *season,
+                episode: episode.saturating_add_signed(self.episode_offset.saturating_neg()),
+            },
+            ep => ep.clone(),
+        }
+    }
+
+    pub fn absolute_numbering(&self) -> bool {
+        self.absolute_numbering
+    }
+
+    /// Declares whether episode numbers count across seasons. Takes effect on
+    /// the next `.update_episodes`.
+    pub fn set_absolute_numbering(&mut self, absolute_numbering: bool) {
+        self.absolute_numbering = absolute_numbering;
+    }
+
+    /// Resolves an episode path against the anime's current directory.
+    ///
+    /// Absolute paths, as stored by older databases, are returned unchanged.
+    /// An empty path is the anime path itself, as used by loose files.
+    pub fn resolve_path(&self, rel: &str) -> PathBuf {
+        match rel {
+            "" => PathBuf::from(&self.path),
+            rel => Path::new(&self.path).join(rel),
+        }
+    }
+
+    /// Gets the path of `ep` with the highest `episode::quality_rank`,
+    /// preferring earlier paths on ties.
+    pub fn best_path_for(&self, ep: &Episode) -> Option<&str> {
+        let (_, paths) = self.episodes.iter().find(|(v, _)| ep.eq(v))?;
+        paths
+            .iter()
+            .rev()
+            .max_by_key(|path| {
+                Path::new(path)
+                    .file_name()
+                    .and_then(|filename| filename.to_str())
+                    .map_or(0, episode::quality_rank)
+            })
+            .map(String::as_str)
+    }
+
+    /// Parses specials again with `parser`, turning those that now parse as
+    /// numbered episodes into them. Returns the number of specials promoted.
+    ///
+    /// Paths of a promoted special are added to the episode if it already
+    /// exists.
+    pub fn reparse_specials(&mut self, parser: &EpisodeParser) -> usize {
+        let (specials, mut episodes): (EpisodeMap, EpisodeMap) = std::mem::take(&mut self.episodes)
+            .into_iter()
+            .partition(|(ep, _)| matches!(ep, Episode::Special { .. }));
+        let mut promoted = 0;
+        for (ep, paths) in specials {
+            let reparsed = match &ep {
+                Episode::Special { filename } => parser.parse(filename).ok(),
+                _ => None,
+            };
+            let ep = match reparsed {
+                Some(reparsed @ Episode::Numbered { .. }) => {
+                    promoted += 1;
+                    reparsed
+                }
+                _ => ep,
+            };
+            match episodes.iter_mut().find(|(v, _)| ep.eq(v)) {
+                Some((_, existing)) => existing.extend(paths),
+                None => episodes.push((ep, paths)),
+            }
+        }
+        episodes.sort_by(|(a, _), (b, _)| a.cmp(b));
+        self.episodes = episodes;
+        promoted
+    }
+
+    /// Removes paths whose content is identical to an earlier path.
+    ///
+    /// Files are compared by length and a hash of their first megabyte, and
+    /// episodes left without any path are removed. Unreadable files are kept.
+    /// Returns the number of removed paths.
+    #[cfg(feature = "dedupe")]
+    pub fn dedupe_by_hash(&mut self) -> usize {
+        use std::collections::hash_map::DefaultHasher;
+        use std::collections::BTreeSet;
+        use std::hash::Hasher;
+
+        const PREFIX_LEN: u64 = 1 << 20;
+
+        let hash = |path: &Path| -> std::io::Result<(u64, u64)> {
+            let f = File::open(path)?;
+            let len = f.metadata()?.len();
+            let mut prefix = Vec::new();
+            f.take(PREFIX_LEN).read_to_end(&mut prefix)?;
+            let mut hasher = DefaultHasher::new();
+            hasher.write(&prefix);
+            Ok((len, hasher.finish()))
+        };
+
+        let mut seen = BTreeSet::new();
+        let mut removed = 0;
+        for i in 0..self.episodes.len() {
+            let paths = std::mem::take(&mut self.episodes[i].1);
+            let (kept, dupes): (Vec<_>, Vec<_>) = paths.into_iter().partition(|path| {
+                hash(&self.resolve_path(path))
+                    .map(|key| seen.insert(key))
+                    .unwrap_or(true)
+            });
+            removed += dupes.len();
+            self.episodes[i].1 = kept;
+        }
+        self.episodes.retain(|(_, paths)| !paths.is_empty());
+        removed
+    }
+
+    /// Gets the files of an episode split into parts, ordered by part number.
+    ///
+    /// Returns an empty list if `ep` does not exist or is not split.
+    pub fn parts_for(&self, ep: &Episode) -> Vec<&str> {
+        let mut parts = self
+            .episodes
+            .iter()
+            .find(|(v, _)| ep.eq(v))
+            .map(|(_, paths)| paths.as_slice())
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|path| {
+                let filename = Path::new(path).file_name()?.to_str()?;
+                Some((episode::parse_part(filename)?, path.as_str()))
+            })
+            .collect::<Vec<_>>();
+        parts.sort();
+        parts.into_iter().map(|(_, path)| path).collect()
+    }
+
+    /// Numbered episodes in order, excluding season 0 and specials.
+    fn absolute_episodes(&self) -> impl Iterator<Item = &Episode> {
+        self.episodes
+            .iter()
+            .map(|(ep, _)| ep)
+            .filter(|ep| matches!(ep, Episode::Numbered { season, .. } if *season > 0))
+    }
+
+    /// Converts a per-season episode into 1-based absolute numbering.
+    ///
+    /// Absolute numbers count the episodes present, so gaps, season 0 and
+    /// specials are skipped.
+    pub fn to_absolute(&self, ep: &Episode) -> Option<usize> {
+        self.absolute_episodes()
+            .position(|v| ep.eq(v))
+            .map(|i| i + 1)
+    }
+
+    /// Converts a 1-based absolute episode number into a per-season episode.
+    pub fn from_absolute(&self, n: usize) -> Option<Episode> {
+        self.absolute_episodes().nth(n.checked_sub(1)?).cloned()
+    }
+
+    /// Gets every episode in watch order, for frontends that index episodes
+    /// by position. Same as `.playlist`.
+    pub fn flat_episodes(&self) -> Vec<&Episode> {
+        self.playlist()
+    }
+
+    /// Gets the episode at 0-based `index` in `.flat_episodes`.
+    pub fn episode_at(&self, index: usize) -> Option<&Episode> {
+        self.flat_episodes().get(index).copied()
+    }
+
+    /// Gets the 0-based index of `ep` in `.flat_episodes`.
+    pub fn episode_index(&self, ep: &Episode) -> Option<usize> {
+        self.flat_episodes().iter().position(|v| ep.eq(v))
+    }
+
+    /// Entries skipped during the last scan, explaining missing episodes.
+    pub fn scan_warnings(&self) -> &[ScanWarning] {
+        &self.warnings
+    }
+
+    /// Video files from the last scan without a recognizable episode number.
+    pub fn unparsed(&self) -> &[PathBuf] {
+        &self.unparsed
+    }
+
+    /// Sets the clock used for `last_watched`. `Database::set_clock` sets it
+    /// for every anime.
+    pub fn set_clock(&mut self, clock: Clock) {
+        self.clock = clock;
+    }
+
+    /// Prefer `.update_watched` because it checks if episode exists in episode_map.
+    pub unsafe fn update_watched_unchecked(&mut self, watched: Episode) {
+        self.last_watched = self.clock.now();
+        self.watched_at.insert(watched.clone(), self.last_watched);
+        self.watched.insert(watched.clone());
+        self.current_episode = watched;
+    }
+
+    pub fn update_watched(&mut self, watched: Episode) -> Result<WatchOutcome> {
+        if !self.episodes.iter().any(|(ep, _)| watched.eq(ep)) {
+            return Err(self.not_exist(watched));
+        }
+        let outcome = match self.last_watched {
+            0 => WatchOutcome::Advanced,
+            _ => match watched.cmp(&self.current_episode) {
+                std::cmp::Ordering::Greater => WatchOutcome::Advanced,
+                std::cmp::Ordering::Equal => WatchOutcome::SameEpisode,
+                std::cmp::Ordering::Less => WatchOutcome::WentBackward,
+            },
+        };
+        unsafe { self.update_watched_unchecked(watched) };
+        Ok(outcome)
+    }
+
+    /// Marks `ep` as watched with `.update_watched` once playback reaches
+    /// `threshold` of the episode, e.g. `0.9` for 90%, for player
+    /// integrations reporting the playback position.
+    ///
+    /// Returns `None` if playback has not reached the threshold, leaving
+    /// progress unchanged.
+    pub fn report_progress(
+        &mut self,
+        ep: Episode,
+        position_secs: f64,
+        duration_secs: f64,
+        threshold: f64,
+    ) -> Result<Option<WatchOutcome>> {
+        if !self.episodes.iter().any(|(v, _)| ep.eq(v)) {
+            return Err(self.not_exist(ep));
+        }
+        if duration_secs > 0.0 && position_secs / duration_secs >= threshold {
+            self.update_watched(ep).map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn not_exist(&self, episode: Episode) -> Err {
+        Err::InvalidEpisode(InvalidEpisodeError::NotExist {
+            anime: self.path.to_string(),
+            episode,
+        })
+    }
+
+    /// Marks every episode up to and including `ep` as watched.
+    ///
+    /// Useful when importing progress from another tracker. Specials are not
+    /// marked, except for `ep` itself.
+    pub fn mark_watched_up_to(&mut self, ep: Episode) -> Result<()> {
+        let position = self
+            .episodes
+            .iter()
+            .position(|(v, _)| ep.eq(v))
+            .ok_or_else(|| self.not_exist(ep.clone()))?;
+        let prior = self.episodes[..position]
+            .iter()
+            .map(|(ep, _)| ep)
+            .filter(|ep| !matches!(ep, Episode::Special { .. }))
+            .cloned()
+            .collect::<Vec<_>>();
+        self.watched.extend(prior);
+        self.update_watched(ep)?;
+        Ok(())
+    }
+
+    /// Marks a single episode as watched or unwatched, without changing
+    /// `.current_episode`.
+    pub fn set_watched(&mut self, ep: Episode, watched: bool) -> Result<()> {
+        if !self.episodes.iter().any(|(v, _)| ep.eq(v)) {
+            return Err(self.not_exist(ep));
+        }
+        if watched {
+            self.watched.insert(ep);
+        } else {
+            self.watched_at.remove(&ep);
+            self.watched.remove(&ep);
+        }
+        Ok(())
+    }
+
+    pub fn is_watched(&self, ep: &Episode) -> bool {
+        self.watched.contains(ep)
+    }
+
+    /// Gets when `ep` was last watched with `.update_watched`, as a Unix
+    /// timestamp. Episodes marked by `.set_watched` or `.mark_watched_up_to`
+    /// have no time unless watched directly.
+    pub fn watched_at(&self, ep: &Episode) -> Option<u64> {
+        self.watched_at.get(ep).copied()
+    }
+
+    /// Gets the length of `ep` set with `.set_duration`.
+    pub fn duration(&self, ep: &Episode) -> Option<Duration> {
+        self.durations
+            .get(ep)
+            .map(|secs| Duration::from_secs(*secs))
+    }
+
+    /// Sets the length of `ep`, used by `Database::total_watch_time`. Lengths
+    /// are stored in whole seconds.
+    pub fn set_duration(&mut self, ep: Episode, duration: Duration) -> Result<()> {
+        if !self.episodes.iter().any(|(v, _)| ep.eq(v)) {
+            return Err(self.not_exist(ep));
+        }
+        self.durations.insert(ep, duration.as_secs());
+        Ok(())
+    }
+
+    /// Sums the lengths of the episodes marked as watched, counting
+    /// `average` for episodes without a known length.
+    fn watch_time(&self, average: Duration) -> Duration {
+        self.watched
+            .iter()
+            .map(|ep| self.duration(ep).unwrap_or(average))
+            .sum()
+    }
+
+    /// Fraction of non-special episodes marked as watched, from 0 to 1.
+    pub fn completion(&self) -> f64 {
+        let (watched, total) = self
+            .episodes
+            .iter()
+            .filter(|(ep, _)| !matches!(ep, Episode::Special { .. }))
+            .fold((0, 0), |(watched, total), (ep, _)| {
+                (watched + self.watched.contains(ep) as usize, total + 1)
+            });
+        match total {
+            0 => 0.0,
+            total => watched as f64 / total as f64,
+        }
+    }
+}
+
+/// Gets the modification time of `path` as a Unix timestamp.
+///
+/// Fails if `path` is gone or its filesystem does not record modification
+/// times, as with some network mounts.
+fn dir_modified_time(path: impl AsRef<Path>) -> std::io::Result<u64> {
+    let modified = metadata(path)?.modified()?;
+    Ok(modified
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |time| time.as_secs()))
+}
+
+/// Checks whether a directory was modified after `last_updated`. Directories
+/// without a modification time are always stale, so they are rescanned rather
+/// than skipped.
+fn is_stale(last_updated: u64, modified: std::io::Result<u64>) -> bool {
+    match modified {
+        Ok(modified) => last_updated < modified,
+        Err(_) => true,
+    }
+}
+
+impl Database {
     /// Note: If database has not been created, then `.init_db()`
     /// must be run before using.
     pub fn new(path: impl AsRef<str>, anime_directories: Vec<impl AsRef<str>>) -> Result<Self> {
+        Self::with_config(path, anime_directories, ScanConfig::default())
+    }
+
+    /// Builds a database from anime assembled in code, without reading or
+    /// scanning anything. It has no path, so use `.write` rather than `.save`.
+    pub fn from_map(anime_map: BTreeMap<String, Anime>) -> Self {
+        Self {
+            anime_map,
+            ..Self::default()
+        }
+    }
+
+    /// Same as `.new`, scanning with `config`.
+    pub fn with_config(
+        path: impl AsRef<str>,
+        anime_directories: Vec<impl AsRef<str>>,
+        config: ScanConfig,
+    ) -> Result<Self> {
         let path = path.as_ref();
         match File::open(path) {
-            Ok(mut v) => {
-                let mut slice = vec![];
-                v.read_to_end(&mut slice)?;
-                Ok(flexbuffers::from_slice::<Self>(&slice)?)
+            Ok(v) => {
+                let mut db = Self::from_reader(v)?;
+                db.config = config;
+                db.path = Some(PathBuf::from(path));
+                Ok(db)
             }
             Err(_) => {
+                #[cfg(feature = "glob")]
+                let anime_directories = Self::expand_directories(&anime_directories)?;
                 let mut db = Self {
-                    anime_map: BTreeMap::new(),
+                    config,
+                    path: Some(PathBuf::from(path)),
+                    ..Self::default()
                 };
                 db.update(anime_directories);
                 Ok(db)
@@ -203,71 +1843,3369 @@ impl Database {
         }
     }
 
+    /// Expands glob patterns in `anime_directories` to the directories they
+    /// match, e.g. `~/Videos/*/anime`. A leading `~` is replaced with `$HOME`.
+    /// Existing directories are kept as they are, so names with brackets such
+    /// as `/mnt/[Anime]` are not read as patterns.
+    ///
+    /// `.new` does this already when it scans; `.update` takes literal
+    /// directories.
+    #[cfg(feature = "glob")]
+    pub fn expand_directories(anime_directories: &[impl AsRef<str>]) -> Result<Vec<String>> {
+        let home = std::env::var("HOME").unwrap_or_default();
+        let mut expanded = Vec::new();
+        for dir in anime_directories {
+            let dir = dir.as_ref();
+            let pattern = match dir.strip_prefix('~') {
+                Some(rest) if rest.is_empty() || rest.starts_with('/') => format!("{home}{rest}"),
+                _ => dir.to_string(),
+            };
+            if Path::new(&pattern).is_dir() {
+                expanded.push(pattern);
+                continue;
+            }
+            expanded.extend(
+                glob::glob(&pattern)?
+                    .filter_map(|path| path.ok())
+                    .filter(|path| path.is_dir())
+                    .map(|path| o_to_str!(path)),
+            );
+        }
+        Ok(expanded)
+    }
+
+    pub fn scan_config(&self) -> &ScanConfig {
+        &self.config
+    }
+
+    pub fn set_scan_config(&mut self, config: ScanConfig) {
+        self.config = config;
+    }
+
+    /// Sets the clock used for timestamps of this database and its anime, e.g.
+    /// a fake clock in tests or `Clock::monotonic`.
+    pub fn set_clock(&mut self, clock: Clock) {
+        for anime in self.anime_map.values_mut() {
+            anime.clock = clock.clone();
+        }
+        self.clock = clock;
+    }
+
+    /// Sets a callback to run whenever `.update_watched` succeeds, so
+    /// integrations can react to progress without polling.
+    pub fn set_on_watch(&mut self, callback: WatchCallback) {
+        self.on_watch = Some(OnWatch(Mutex::new(callback)));
+    }
+
+    /// Same as `Anime::update_watched`, then notifies the `.set_on_watch`
+    /// callback.
+    pub fn update_watched(
+        &mut self,
+        anime: impl AsRef<str>,
+        watched: Episode,
+    ) -> Result<WatchOutcome> {
+        let name = anime.as_ref();
+        let Some(anime) = self.anime_map.get_mut(&*self.config.key(name)) else {
+            return Err(Err::InvalidEpisode(InvalidEpisodeError::NotExist {
+                anime: name.to_string(),
+                episode: watched,
+            }));
+        };
+        let outcome = anime.update_watched(watched.clone())?;
+        if let Some(OnWatch(callback)) = &mut self.on_watch {
+            let callback = callback.get_mut().unwrap_or_else(PoisonError::into_inner);
+            callback(name, &watched);
+        }
+        Ok(outcome)
+    }
+
     pub fn update(&mut self, anime_directories: Vec<impl AsRef<str>>) {
-        let time = get_time();
-        anime_directories
+        let time = self.clock.now();
+        self.unreadable = anime_directories
             .iter()
-            .filter_map(|s| read_dir(s.as_ref()).ok())
-            .flat_map(|s| {
-                s.filter_map(|v| v.ok())
-                    .map(|v| (o_to_str!(v.file_name()), v.path()))
+            .filter_map(|dir| {
+                let error = read_dir(dir.as_ref()).err()?;
+                Some((PathBuf::from(dir.as_ref()), error.to_string()))
             })
+            .collect();
+        self.anime_entries(&anime_directories)
+            .into_iter()
             .for_each(|(name, path)| {
                 match self.anime_map.entry(name) {
+                    Entry::Vacant(_) if self.config.in_progress(&path, time) => {}
                     Entry::Vacant(v) => {
-                        v.insert(Anime::from_path(path, time));
+                        let mut anime = Anime::with_episodes(path, Vec::new());
+                        anime.clock = self.clock.clone();
+                        anime.last_updated = time;
+                        anime.date_added = time;
+                        anime.update_episodes_with(&self.config);
+                        if anime.total_paths() >= self.config.min_episodes {
+                            v.insert(anime);
+                        }
                     }
                     Entry::Occupied(mut v) => {
-                        if v.get().last_updated < dir_modified_time(path) {
-                            v.get_mut().update_episodes();
+                        let relocated = Path::new(&v.get().path) != path;
+                        if relocated {
+                            v.get_mut().path = o_to_str!(path);
+                        }
+                        let changed =
+                            relocated || is_stale(v.get().last_updated, dir_modified_time(&path));
+                        if changed && !self.config.in_progress(&path, time) {
+                            v.get_mut().update_episodes_with(&self.config);
+                            v.get_mut().last_updated = time;
                         }
                     }
                 };
             });
     }
 
-    pub fn write(&mut self, path: impl AsRef<Path>) -> Result<()> {
-        let mut f = File::create(path)?;
-        let mut s = flexbuffers::FlexbufferSerializer::new();
-        self.serialize(&mut s).unwrap();
-        f.write_all(s.view())?;
-        Ok(())
+    /// Rescans every anime not updated in the last `max_age_secs`, regardless
+    /// of modification times. Returns the number rescanned.
+    pub fn rescan_stale(&mut self, max_age_secs: u64) -> usize {
+        let time = self.clock.now();
+        let mut rescanned = 0;
+        for anime in self.anime_map.values_mut() {
+            if time.saturating_sub(anime.last_updated) > max_age_secs {
+                anime.update_episodes_with(&self.config);
+                anime.last_updated = time;
+                rescanned += 1;
+            }
+        }
+        rescanned
     }
 
-    pub fn animes(&mut self) -> Result<Box<[(&String, &mut Anime)]>> {
-        let mut anime_list = self
+    /// Re-keys anime whose key no longer matches their folder name, e.g. after
+    /// a folder was renamed outside the app. Returns the number re-keyed.
+    ///
+    /// If the folder name is already a key, the entries are merged.
+    pub fn repair_keys(&mut self) -> usize {
+        let stale = self
             .anime_map
-            .iter_mut()
-            .collect::<Box<[(&String, &mut Anime)]>>();
-        anime_list.sort_by(|(_, a), (_, b)| b.last_watched.cmp(&a.last_watched));
+            .iter()
+            .filter_map(|(name, anime)| {
+                let path = Path::new(&anime.path);
+                let current = match path.file_stem() {
+                    Some(stem) if self.config.loose_files && path.is_file() => stem,
+                    _ => path.file_name()?,
+                };
+                let current = self.config.key(&o_to_str!(current)).into_owned();
+                (*name != current).then(|| (name.to_owned(), current))
+            })
+            .collect::<Vec<_>>();
+        for (old, current) in &stale {
+            let anime = self.anime_map.remove(old).expect("Name is a key");
+            match self.anime_map.entry(current.to_owned()) {
+                Entry::Vacant(v) => {
+                    v.insert(anime);
+                }
+                Entry::Occupied(mut v) => v.get_mut().merge(anime),
+            }
+        }
+        stale.len()
+    }
 
-        Ok(anime_list)
+    /// Checks whether `.update` with `anime_directories` would change
+    /// anything, without walking into each anime.
+    ///
+    /// This is the case if an anime was added, removed, moved or modified
+    /// since it was last scanned.
+    pub fn needs_update(&self, anime_directories: Vec<impl AsRef<str>>) -> bool {
+        let entries = self.anime_entries(&anime_directories);
+        let changed = entries
+            .iter()
+            .any(|(name, path)| match self.anime_map.get(name) {
+                Some(anime) => {
+                    Path::new(&anime.path) != path
+                        || is_stale(anime.last_updated, dir_modified_time(path))
+                }
+                None => true,
+            });
+        let removed = self.anime_map.iter().any(|(name, anime)| {
+            let parent = Path::new(&anime.path).parent();
+            anime_directories
+                .iter()
+                .any(|dir| parent == Some(Path::new(dir.as_ref())))
+                && !entries.iter().any(|(v, _)| v == name)
+        });
+        changed || removed
     }
 
-    pub fn get_anime<'a>(&'a mut self, anime: impl AsRef<str>) -> Option<&'a mut Anime> {
-        let anime = anime.as_ref().to_string();
-        self.anime_map.get_mut(&anime)
+    /// Lists the anime in `anime_directories` by name.
+    fn anime_entries(&self, anime_directories: &[impl AsRef<str>]) -> Vec<(String, PathBuf)> {
+        anime_directories
+            .iter()
+            .filter_map(|s| read_dir(s.as_ref()).ok())
+            .flat_map(|s| {
+                s.filter_map(|v| v.ok()).map(|v| {
+                    let path = v.path();
+                    match path.file_stem() {
+                        Some(stem)
+                            if self.config.loose_files && path.is_file() && is_video(&path) =>
+                        {
+                            (self.config.key(&o_to_str!(stem)).into_owned(), path)
+                        }
+                        _ => (
+                            self.config.key(&o_to_str!(v.file_name())).into_owned(),
+                            path,
+                        ),
+                    }
+                })
+            })
+            .collect()
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use std::collections::BTreeMap;
+    /// Updates the database from `anime_directories` and summarizes the result.
+    ///
+    /// Unparsed files are only known for anime scanned during this session.
+    pub fn scan_report(&mut self, anime_directories: Vec<impl AsRef<str>>) -> ScanReport {
+        self.update(anime_directories);
+        self.anime_map
+            .iter()
+            .fold(ScanReport::default(), |mut report, (name, anime)| {
+                if anime.episodes.is_empty() {
+                    report.empty.push(name.to_owned());
+                }
+                report.anime += 1;
+                report.episodes += anime.episodes.len();
+                report.unparsed += anime.unparsed.len();
+                report.duplicates += anime
+                    .episodes
+                    .iter()
+                    .filter(|(_, paths)| paths.len() > 1)
+                    .count();
+                report
+            })
+    }
 
-    #[test]
-    fn btree_test() {
-        let btree = [("hello", 20), ("hi", 5), ("hello", 1)].into_iter().fold(
-            BTreeMap::new(),
-            |mut acc, (k, v)| {
-                acc.entry(k)
-                    .and_modify(|list: &mut Vec<usize>| list.push(v))
-                    .or_insert(vec![v]);
-                acc
-            },
-        );
-        assert_eq!(
+    /// Collects the problems from the last `.update` in one place.
+    ///
+    /// File problems are only known for anime scanned during this session.
+    pub fn scan_errors(&self) -> ScanErrors {
+        let mut errors = ScanErrors {
+            unreadable_dirs: self.unreadable.clone(),
+            ..ScanErrors::default()
+        };
+        for (name, anime) in &self.anime_map {
+            for warning in &anime.warnings {
+                match warning {
+                    ScanWarning::Unreadable { path, error } if path.is_dir() => {
+                        errors.unreadable_dirs.push((path.clone(), error.clone()))
+                    }
+                    ScanWarning::Unreadable { path, error } => {
+                        errors.unreadable_files.push((path.clone(), error.clone()))
+                    }
+                    ScanWarning::NotAFile { path } => errors
+                        .unreadable_files
+                        .push((path.clone(), "Not a regular file".to_string())),
+                    ScanWarning::CurrentEpisodeMissing { episode, moved_to } => errors
+                        .moved_progress
+                        .push((name.to_owned(), episode.clone(), moved_to.clone())),
+                }
+            }
+            errors.unparsed.extend(anime.unparsed.iter().cloned());
+            if anime.episodes.is_empty() {
+                errors.empty.push(name.to_owned());
+            }
+        }
+        errors
+    }
+
+    /// Counts the anime in each watch status.
+    pub fn status_summary(&self) -> BTreeMap<WatchStatus, usize> {
+        self.anime_map
+            .values()
+            .fold(BTreeMap::new(), |mut summary, anime| {
+                *summary.entry(anime.status).or_default() += 1;
+                summary
+            })
+    }
+
+    /// Imports progress from a MyAnimeList XML export at `path`.
+    ///
+    /// Titles are matched as in `.get_anime_fuzzy`. Matched anime get their
+    /// status, score and watched episodes, counted in absolute numbering. An
+    /// unrated entry (score 0) keeps the local score. Unmatched titles are reported, not added.
+    pub fn import_mal_xml(&mut self, path: impl AsRef<Path>) -> Result<ImportReport> {
+        let xml = std::fs::read_to_string(path)?;
+        let mut report = ImportReport::default();
+        for entry in parse_mal_xml(&xml)? {
+            let Some(name) = self.fuzzy_name(&entry.title).cloned() else {
+                report.unmatched.push(entry.title);
+                continue;
+            };
+            let anime = self.anime_map.get_mut(&name).expect("Name is a key");
+            if let Some(status) = entry.status {
+                anime.status = status;
+            }
+            if entry.score > 0 {
+                anime.score = Some(entry.score);
+            }
+            let available = anime.absolute_episodes().count();
+            if let Some(last) = anime.from_absolute(entry.watched_episodes.min(available)) {
+                anime.mark_watched_up_to(last)?;
+            }
+            report.matched.push(name);
+        }
+        Ok(report)
+    }
+
+    /// Lists anime that have episodes after the last one watched, with how
+    /// many there are.
+    ///
+    /// Specials and anime that were never started are not counted.
+    pub fn with_new_episodes(&self) -> Vec<(&String, usize)> {
+        self.anime_map
+            .iter()
+            .filter_map(|(name, anime)| {
+                let last = anime.last_finished()?;
+                let new = anime
+                    .episodes
+                    .iter()
+                    .filter(|(ep, _)| !matches!(ep, Episode::Special { .. }) && *ep > last)
+                    .count();
+                (new > 0).then_some((name, new))
+            })
+            .collect()
+    }
+
+    /// Finds episodes whose title, from `episode::episode_title`, contains
+    /// `query`, ignoring case.
+    pub fn search_episodes(&self, query: &str) -> Vec<(&String, &Episode)> {
+        let query = query.to_lowercase();
+        self.anime_map
+            .iter()
+            .flat_map(|(name, anime)| {
+                anime
+                    .episodes
+                    .iter()
+                    .filter(|(_, paths)| {
+                        paths.iter().any(|path| {
+                            Path::new(path)
+                                .file_name()
+                                .and_then(|filename| filename.to_str())
+                                .and_then(episode::episode_title)
+                                .is_some_and(|title| title.to_lowercase().contains(&query))
+                        })
+                    })
+                    .map(move |(ep, _)| (name, ep))
+            })
+            .collect()
+    }
+
+    /// Copies the database with file system locations removed, for sharing.
+    ///
+    /// Anime paths are replaced by their names and episode paths by their
+    /// file names. Episodes and progress are kept.
+    pub fn anonymized(&self) -> Database {
+        let anime_map = self
+            .anime_map
+            .iter()
+            .map(|(name, anime)| {
+                let mut anime = anime.clone();
+                anime.path = name.to_owned();
+                anime.unparsed.clear();
+                anime.warnings.clear();
+                for (_, paths) in anime.episodes.iter_mut().chain(&mut anime.archived) {
+                    for path in paths.iter_mut() {
+                        if let Some(filename) = Path::new(path).file_name() {
+                            *path = o_to_str!(filename);
+                        }
+                    }
+                }
+                (name.to_owned(), anime)
+            })
+            .collect();
+        Database {
+            anime_map,
+            config: self.config.clone(),
+            on_watch: None,
+            path: None,
+            unreadable: Vec::new(),
+            clock: self.clock.clone(),
+        }
+    }
+
+    /// Groups anime that are seasons of the same series, keyed by series title.
+    ///
+    /// Titles are compared with `episode::series_title`, ignoring case.
+    pub fn franchises(&self) -> BTreeMap<String, Vec<&String>> {
+        let mut groups: BTreeMap<String, (String, Vec<&String>)> = BTreeMap::new();
+        for name in self.anime_map.keys() {
+            let title = episode::series_title(name);
+            groups
+                .entry(title.to_lowercase())
+                .or_insert_with(|| (title, Vec::new()))
+                .1
+                .push(name);
+        }
+        groups.into_values().collect()
+    }
+
+    /// Finds episode files indexed under more than one anime, e.g. through
+    /// symlinks or overlapping anime directories, with the names of those
+    /// anime. Paths are canonicalized when the file exists.
+    pub fn duplicate_paths(&self) -> Vec<(String, Vec<String>)> {
+        self.shared_paths()
+            .into_iter()
+            .map(|(path, names)| (o_to_str!(path), names))
+            .collect()
+    }
+
+    /// Episode files, canonicalized when they exist, that are indexed under
+    /// more than one anime, with the sorted names of those anime.
+    fn shared_paths(&self) -> Vec<(PathBuf, Vec<String>)> {
+        let mut owners: BTreeMap<PathBuf, BTreeSet<&String>> = BTreeMap::new();
+        for (name, anime) in &self.anime_map {
+            for path in anime.episodes.iter().flat_map(|(_, paths)| paths) {
+                let path = anime.resolve_path(path);
+                let path = path.canonicalize().unwrap_or(path);
+                owners.entry(path).or_default().insert(name);
+            }
+        }
+        owners
+            .into_iter()
+            .filter(|(_, names)| names.len() > 1)
+            .map(|(path, names)| (path, names.into_iter().cloned().collect()))
+            .collect()
+    }
+
+    /// Counts how many anime have files from each release group, as found by
+    /// `episode::release_group`. An anime counts once per group.
+    pub fn release_groups(&self) -> BTreeMap<String, usize> {
+        let mut counts = BTreeMap::new();
+        for anime in self.anime_map.values() {
+            let groups = anime
+                .episodes
+                .iter()
+                .flat_map(|(_, paths)| paths)
+                .filter_map(|path| Path::new(path).file_name()?.to_str())
+                .filter_map(episode::release_group)
+                .collect::<BTreeSet<_>>();
+            for group in groups {
+                *counts.entry(group.to_string()).or_insert(0) += 1;
+            }
+        }
+        counts
+    }
+
+    /// Repairs every anime after bulk edits or migrations: sorts episodes and
+    /// their paths as a scan would, merges repeated episodes and moves
+    /// progress off episodes that no longer exist.
+    ///
+    /// Completion and listings such as `.animes` are computed when asked for,
+    /// so they need no rebuilding.
+    pub fn rebuild_indexes(&mut self) {
+        for anime in self.anime_map.values_mut() {
+            anime.rebuild_indexes();
+        }
+    }
+
+    /// Checks the database for structural problems.
+    pub fn validate(&self) -> Vec<ValidationIssue> {
+        let mut issues = Vec::new();
+        for (name, anime) in self.anime_map.iter() {
+            if anime.episodes.is_empty() {
+                issues.push(ValidationIssue::NoEpisodes {
+                    anime: name.to_owned(),
+                });
+            }
+            if !anime
+                .episodes
+                .iter()
+                .any(|(ep, _)| anime.current_episode.eq(ep))
+            {
+                issues.push(ValidationIssue::InvalidCurrentEpisode {
+                    anime: name.to_owned(),
+                    episode: anime.current_episode.clone(),
+                });
+            }
+            for (ep, paths) in anime.episodes.iter() {
+                for path in paths {
+                    if !anime.resolve_path(path).exists() {
+                        issues.push(ValidationIssue::MissingPath {
+                            anime: name.to_owned(),
+                            episode: ep.clone(),
+                            path: path.to_owned(),
+                        });
+                    }
+                }
+            }
+        }
+        issues.extend(self.shared_paths().into_iter().map(|(path, anime)| {
+            ValidationIssue::DuplicatePath {
+                path: o_to_str!(path),
+                anime,
+            }
+        }));
+        issues
+    }
+
+    /// Writes the database to `path`.
+    ///
+    /// Flexbuffers is built back to front, so it cannot be streamed to the
+    /// file; the whole database is serialized into one buffer (roughly the
+    /// size of the file) before writing. The file is only created once
+    /// serialization succeeds, so a failure leaves any existing database intact.
+    pub fn write(&mut self, path: impl AsRef<Path>) -> Result<()> {
+        let s = self.serializer()?;
+        let mut f = File::create(path)?;
+        f.write_all(s.view())?;
+        Ok(())
+    }
+
+    /// Gets the file the database was opened from with `.new`.
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// Writes the database back to `.path`. Use `.write` to save elsewhere.
+    pub fn save(&mut self) -> Result<()> {
+        let path = self.path.clone().ok_or(Err::NoPath)?;
+        self.write(path)
+    }
+
+    /// Loads the database at `path` without scanning, for viewers that must
+    /// never modify it.
+    ///
+    /// Unlike `.new`, a missing file is an error rather than a new database.
+    pub fn open_readonly(path: impl AsRef<Path>) -> Result<ReadOnlyDatabase> {
+        Ok(ReadOnlyDatabase(Self::from_reader(File::open(path)?)?))
+    }
+
+    /// Reads a database serialized by `.to_writer` or `.write`.
+    ///
+    /// Fields this version does not know about are ignored, so databases
+    /// written by newer versions can still be read. Do not add
+    /// `#[serde(deny_unknown_fields)]` to the stored types.
+    pub fn from_reader(mut reader: impl Read) -> Result<Self> {
+        let mut slice = vec![];
+        reader.read_to_end(&mut slice)?;
+        Ok(flexbuffers::from_slice::<Self>(&slice)?)
+    }
+
+    /// Writes the database to `writer` in the same format as `.write`.
+    pub fn to_writer(&self, mut writer: impl Write) -> Result<()> {
+        writer.write_all(self.serializer()?.view())?;
+        Ok(())
+    }
+
+    /// Same as `Anime::to_json` for the anime `name`, e.g. to share progress
+    /// on one show. Returns `None` if there is no such anime.
+    pub fn export_anime(&self, name: impl AsRef<str>) -> Option<String> {
+        self.anime(name)?.to_json().ok()
+    }
+
+    /// Writes every episode as a JSON object on its own line, for piping into
+    /// external tools.
+    ///
+    /// Each line has `anime`, `season`, `episode`, `special` and `path` fields.
+    /// Episodes that are not numbered have null `season` and `episode`, with
+    /// `special` holding the filename or air date. `path` is the episode's first
+    /// file relative to the anime's path.
+    pub fn export_episodes_jsonl(&self, mut writer: impl Write) -> Result<()> {
+        #[derive(Serialize)]
+        struct Line<'a> {
+            anime: &'a str,
+            season: Option<u32>,
+            episode: Option<u32>,
+            special: Option<String>,
+            path: Option<&'a str>,
+        }
+
+        for (name, anime) in &self.anime_map {
+            for (ep, paths) in &anime.episodes {
+                let (season, episode, special) = match ep {
+                    Episode::Numbered { season, episode } => (Some(*season), Some(*episode), None),
+                    _ => (None, None, Some(ep.to_string())),
+                };
+                let line = Line {
+                    anime: name,
+                    season,
+                    episode,
+                    special,
+                    path: paths.first().map(String::as_str),
+                };
+                serde_json::to_writer(&mut writer, &line)?;
+                writer.write_all(b"\n")?;
+            }
+        }
+        Ok(())
+    }
+
+    fn serializer(&self) -> Result<flexbuffers::FlexbufferSerializer> {
+        let mut s = flexbuffers::FlexbufferSerializer::new();
+        self.serialize(&mut s)?;
+        Ok(s)
+    }
+
+    /// Backfills watch history of never-started anime from the access times
+    /// of their episode files, for collections watched before using the
+    /// database. Returns the number of anime updated.
+    ///
+    /// `last_watched` becomes the most recent access time, and the episode
+    /// accessed last becomes the current episode. Access times are only
+    /// meaningful if the filesystem records them, e.g. not with `noatime`.
+    #[cfg(feature = "atime")]
+    pub fn backfill_from_atime(&mut self) -> usize {
+        let accessed = |path: &Path| -> Option<u64> {
+            let time = metadata(path).ok()?.accessed().ok()?;
+            Some(time.duration_since(SystemTime::UNIX_EPOCH).ok()?.as_secs())
+        };
+
+        let mut updated = 0;
+        for anime in self.anime_map.values_mut() {
+            if anime.last_watched != 0 {
+                continue;
+            }
+            let latest = anime
+                .episodes
+                .iter()
+                .flat_map(|(ep, paths)| paths.iter().map(move |path| (ep, path)))
+                .filter_map(|(ep, path)| Some((accessed(&anime.resolve_path(path))?, ep)))
+                .max_by_key(|(time, _)| *time);
+            if let Some((time, ep)) = latest {
+                anime.current_episode = ep.clone();
+                anime.last_watched = time;
+                updated += 1;
+            }
+        }
+        updated
+    }
+
+    /// Counts the episode files of every anime, as in `Anime::total_paths`.
+    pub fn total_files(&self) -> usize {
+        self.anime_map.values().map(Anime::total_paths).sum()
+    }
+
+    /// Iterates over the episodes of every anime, by anime name and then in
+    /// episode order.
+    pub fn all_episodes(&self) -> impl Iterator<Item = (&String, &Episode)> + '_ {
+        self.anime_map
+            .iter()
+            .flat_map(|(name, anime)| anime.episodes.iter().map(move |(ep, _)| (name, ep)))
+    }
+
+    /// Gets the names of all anime in alphabetical order.
+    pub fn anime_names(&self) -> Vec<&str> {
+        self.anime_map.keys().map(String::as_str).collect()
+    }
+
+    /// Lists anime by most recently watched, then by name.
+    pub fn animes(&mut self) -> Result<Box<[(&String, &mut Anime)]>> {
+        let mut anime_list = self
+            .anime_map
+            .iter_mut()
+            .collect::<Box<[(&String, &mut Anime)]>>();
+        anime_list.sort_by(|(name_a, a), (name_b, b)| {
+            b.last_watched
+                .cmp(&a.last_watched)
+                .then_with(|| name_a.cmp(name_b))
+        });
+
+        Ok(anime_list)
+    }
+
+    /// Estimates the time spent watching every episode marked as watched,
+    /// counting `DEFAULT_EPISODE_LENGTH` for episodes without a length set
+    /// with `Anime::set_duration`.
+    pub fn total_watch_time(&self) -> Duration {
+        self.total_watch_time_with(DEFAULT_EPISODE_LENGTH)
+    }
+
+    /// Same as `.total_watch_time`, counting `average` for episodes without a
+    /// known length.
+    pub fn total_watch_time_with(&self, average: Duration) -> Duration {
+        self.anime_map
+            .values()
+            .map(|anime| anime.watch_time(average))
+            .sum()
+    }
+
+    /// Lists anime that were never started, oldest additions first.
+    pub fn backlog(&self) -> Vec<(&String, &Anime)> {
+        let mut backlog = self
+            .anime_map
+            .iter()
+            .filter(|(_, anime)| anime.last_watched == 0)
+            .collect::<Vec<_>>();
+        backlog.sort_by_key(|(_, anime)| anime.date_added);
+        backlog
+    }
+
+    /// Lists anime the user has not touched: never watched, never rated and
+    /// still in the default status. Useful for a library setup view.
+    pub fn unconfigured(&self) -> Vec<&String> {
+        self.anime_map
+            .iter()
+            .filter(|(_, anime)| {
+                anime.last_watched == 0
+                    && anime.score.is_none()
+                    && anime.status == WatchStatus::default()
+            })
+            .map(|(name, _)| name)
+            .collect()
+    }
+
+    /// Same as `.get_anime`, without needing `&mut self`.
+    pub fn anime(&self, anime: impl AsRef<str>) -> Option<&Anime> {
+        self.anime_map.get(&*self.config.key(anime.as_ref()))
+    }
+
+    pub fn get_anime<'a>(&'a mut self, anime: impl AsRef<str>) -> Option<&'a mut Anime> {
+        let anime = self.config.key(anime.as_ref()).into_owned();
+        self.anime_map.get_mut(&anime)
+    }
+
+    /// Same as `.get_anime`, falling back to a case-insensitive match of
+    /// titles cleaned with `episode::clean_title`.
+    ///
+    /// Returns `None` if the query matches no anime or more than one.
+    pub fn get_anime_fuzzy(&mut self, query: &str) -> Option<&mut Anime> {
+        let name = self.fuzzy_name(query)?.to_owned();
+        self.anime_map.get_mut(&name)
+    }
+
+    /// Gets the name of the anime `.get_anime_fuzzy` would return.
+    fn fuzzy_name(&self, query: &str) -> Option<&String> {
+        if let Some((name, _)) = self.anime_map.get_key_value(&*self.config.key(query)) {
+            return Some(name);
+        }
+        let query = episode::clean_title(query);
+        let mut matches = self
+            .anime_map
+            .keys()
+            .filter(|name| episode::clean_title(name) == query);
+        match (matches.next(), matches.next()) {
+            (Some(name), None) => Some(name),
+            _ => None,
+        }
+    }
+
+    /// Gets the episode to autoplay after `after`, with its anime name and
+    /// resolved path.
+    ///
+    /// Specials are skipped. Returns `None` once the anime is finished; what
+    /// to play next is left to the caller.
+    pub fn next_in_queue(&self, after: (&str, &Episode)) -> Option<(String, Episode, String)> {
+        let (name, after) = after;
+        let anime = self.anime(name)?;
+        let (ep, paths) = anime
+            .episodes
+            .iter()
+            .find(|(ep, _)| !matches!(ep, Episode::Special { .. }) && ep > after)?;
+        let path = anime.resolve_path(paths.first()?);
+        Some((name.to_owned(), ep.clone(), o_to_str!(path)))
+    }
+
+    /// Gets the first path of `ep` in `anime`, relative to the anime's path.
+    ///
+    /// Use `Anime::resolve_path` to get a path that can be opened.
+    pub fn episode_path(&self, anime: &str, ep: &Episode) -> Option<&str> {
+        self.anime(anime)?
+            .episodes
+            .iter()
+            .find(|(v, _)| ep.eq(v))?
+            .1
+            .first()
+            .map(String::as_str)
+    }
+}
+
+/// Database opened with `Database::open_readonly`.
+///
+/// Only `&self` methods of `Database` are reachable, so it cannot be
+/// updated, rescanned or written back.
+#[derive(Debug)]
+pub struct ReadOnlyDatabase(Database);
+
+impl std::ops::Deref for ReadOnlyDatabase {
+    type Target = Database;
+
+    fn deref(&self) -> &Database {
+        &self.0
+    }
+}
+
+impl ReadOnlyDatabase {
+    pub fn get_anime(&self, anime: impl AsRef<str>) -> Option<&Anime> {
+        self.0.anime(anime)
+    }
+}
+
+/// Database that can be cloned and shared between threads.
+///
+/// Any number of threads may read at once, while writes are exclusive.
+#[derive(Debug, Clone, Default)]
+pub struct SharedDatabase(Arc<RwLock<Database>>);
+
+impl SharedDatabase {
+    pub fn new(db: Database) -> Self {
+        Self(Arc::new(RwLock::new(db)))
+    }
+
+    /// Locks the database for reading, blocking while it is written to.
+    ///
+    /// A thread that panicked while writing does not make the database
+    /// unusable; the lock is recovered.
+    pub fn read(&self) -> RwLockReadGuard<'_, Database> {
+        self.0.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Locks the database for writing, blocking while it is read or written.
+    pub fn write(&self) -> RwLockWriteGuard<'_, Database> {
+        self.0.write().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl From<Database> for SharedDatabase {
+    fn from(db: Database) -> Self {
+        Self::new(db)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+    use std::fs;
+
+    /// Creates an empty file for each of `files` under a fresh temporary directory.
+    fn fixture(name: &str, files: &[&str]) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("anime-database-lib-{name}"));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        for file in files {
+            let path = root.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            File::create(path).unwrap();
+        }
+        root
+    }
+
+    fn anime(path: impl AsRef<Path>, episodes: EpisodeMap) -> Anime {
+        Anime::with_episodes(path, episodes)
+    }
+
+    fn empty_db() -> Database {
+        Database::default()
+    }
+
+    #[test]
+    fn btree_test() {
+        let btree = [("hello", 20), ("hi", 5), ("hello", 1)].into_iter().fold(
+            BTreeMap::new(),
+            |mut acc, (k, v)| {
+                acc.entry(k)
+                    .and_modify(|list: &mut Vec<usize>| list.push(v))
+                    .or_insert(vec![v]);
+                acc
+            },
+        );
+        assert_eq!(
             BTreeMap::from([("hello", vec![20, 1]), ("hi", vec![5])]),
             btree
         );
     }
+
+    #[test]
+    #[cfg(unix)]
+    fn scan_report_counts() {
+        use std::os::unix::ffi::OsStrExt;
+
+        let root = fixture(
+            "scan-report",
+            &[
+                "Vinland Saga/[sam] Vinland Saga - 01 [BD 1080p FLAC].mkv",
+                "Vinland Saga/[sam] Vinland Saga - 02 [BD 1080p FLAC].mkv",
+                "Vinland Saga/[other] Vinland Saga - 02 [720p].mp4",
+                "Vinland Saga/notes.txt",
+                "Yuyushiki/[Datte13] Yuyushiki - S01E01 - Title.mkv",
+                "Yuyushiki/Movie.mkv",
+            ],
+        );
+        let invalid = std::ffi::OsStr::from_bytes(b"Yuyushiki - S01E02 \xff.mkv");
+        File::create(root.join("Yuyushiki").join(invalid)).unwrap();
+
+        let mut db = empty_db();
+        let report = db.scan_report(vec![root.to_str().unwrap()]);
+        assert_eq!(
+            ScanReport {
+                anime: 2,
+                episodes: 5,
+                unparsed: 1,
+                duplicates: 1,
+                empty: Vec::new(),
+            },
+            report
+        );
+    }
+
+    #[test]
+    fn parts_for_split_episodes() {
+        let root = fixture(
+            "parts-for",
+            &[
+                "Show/[Group] Show - 05b.mkv",
+                "Show/[Group] Show - 05a.mkv",
+                "Show/[Group] Show - 06 - Part 2.mkv",
+                "Show/[Group] Show - 06 - Part 1.mkv",
+                "Show/[Group] Show - 07.mkv",
+            ],
+        );
+        let anime = Anime::from_path(root.join("Show"), 0);
+        let filenames = |ep| {
+            anime
+                .parts_for(&Episode::from(ep))
+                .into_iter()
+                .map(|p| Path::new(p).file_name().unwrap().to_str().unwrap())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(3, anime.episodes().len());
+        assert_eq!(
+            vec!["[Group] Show - 05a.mkv", "[Group] Show - 05b.mkv"],
+            filenames((1, 5))
+        );
+        assert_eq!(
+            vec![
+                "[Group] Show - 06 - Part 1.mkv",
+                "[Group] Show - 06 - Part 2.mkv"
+            ],
+            filenames((1, 6))
+        );
+        assert!(filenames((1, 7)).is_empty());
+    }
+
+    #[test]
+    fn episode_offset() {
+        let root = fixture(
+            "episode-offset",
+            &[
+                "Show/Show - 00.mkv",
+                "Show/Show - 01.mkv",
+                "Show/Show - 02.mkv",
+            ],
+        );
+        let mut show = Anime::from_path(root.join("Show"), 0);
+        show.update_watched(Episode::from((1, 0))).unwrap();
+        assert_eq!("Show: S01E00 / 3 (Plan to Watch)", show.to_string());
+
+        show.set_offset(1);
+        assert_eq!("Show: S01E01 / 3 (Plan to Watch)", show.to_string());
+        assert_eq!(
+            Episode::from((1, 3)),
+            show.display_episode(&Episode::from((1, 2)))
+        );
+        let raw = show.stored_episode(&Episode::from((1, 1)));
+        assert_eq!(Episode::from((1, 0)), raw);
+        assert_eq!(Some("Show - 00.mkv"), show.best_path_for(&raw));
+
+        show.set_offset(-1);
+        assert_eq!(
+            Episode::from((1, 0)),
+            show.display_episode(&Episode::from((1, 0)))
+        );
+    }
+
+    #[test]
+    fn rebuild_indexes_restores_order() {
+        let mut show = anime("/nonexistent/Show", Vec::new());
+        show.episodes = vec![
+            (Episode::from((1, 2)), vec!["Show - 02.mkv".to_string()]),
+            (
+                Episode::from((1, 1)),
+                vec![
+                    "Show - 01 [720p].mkv".to_string(),
+                    "Show - 01 [1080p].mkv".to_string(),
+                ],
+            ),
+            (
+                Episode::from((1, 2)),
+                vec![
+                    "Show - 02 [1080p].mkv".to_string(),
+                    "Show - 02.mkv".to_string(),
+                ],
+            ),
+        ];
+        show.current_episode = Episode::from((1, 5));
+        show.last_watched = 100;
+        let mut db = empty_db();
+        db.anime_map.insert("Show".to_string(), show);
+
+        db.rebuild_indexes();
+        let show = db.anime("Show").unwrap();
+        assert_eq!(
+            show.episodes,
+            vec![
+                (
+                    Episode::from((1, 1)),
+                    vec![
+                        "Show - 01 [1080p].mkv".to_string(),
+                        "Show - 01 [720p].mkv".to_string(),
+                    ]
+                ),
+                (
+                    Episode::from((1, 2)),
+                    vec![
+                        "Show - 02 [1080p].mkv".to_string(),
+                        "Show - 02.mkv".to_string(),
+                    ]
+                ),
+            ]
+        );
+        assert_eq!(Episode::from((1, 2)), show.current_episode());
+    }
+
+    #[test]
+    fn validate_reports_issues() {
+        let root = fixture("validate", &["A/A - 01.mkv", "B/B - 01.mkv"]);
+        let path = |p: &str| o_to_str!(root.join(p));
+        let mut db = empty_db();
+        db.anime_map.insert(
+            "A".to_string(),
+            anime(
+                root.join("A"),
+                vec![
+                    (Episode::from((1, 1)), vec![path("A/A - 01.mkv")]),
+                    (Episode::from((1, 2)), vec![path("A/A - 02.mkv")]),
+                ],
+            ),
+        );
+        let mut b = anime(
+            root.join("B"),
+            vec![(
+                Episode::from((1, 1)),
+                vec![path("B/B - 01.mkv"), path("A/A - 01.mkv")],
+            )],
+        );
+        b.current_episode = Episode::from((1, 5));
+        db.anime_map.insert("B".to_string(), b);
+        db.anime_map
+            .insert("C".to_string(), anime(root.join("C"), Vec::new()));
+
+        assert_eq!(
+            vec![
+                ValidationIssue::MissingPath {
+                    anime: "A".to_string(),
+                    episode: Episode::from((1, 2)),
+                    path: path("A/A - 02.mkv"),
+                },
+                ValidationIssue::InvalidCurrentEpisode {
+                    anime: "B".to_string(),
+                    episode: Episode::from((1, 5)),
+                },
+                ValidationIssue::NoEpisodes {
+                    anime: "C".to_string(),
+                },
+                ValidationIssue::InvalidCurrentEpisode {
+                    anime: "C".to_string(),
+                    episode: Episode::from((1, 1)),
+                },
+                ValidationIssue::DuplicatePath {
+                    path: o_to_str!(root.join("A/A - 01.mkv").canonicalize().unwrap()),
+                    anime: vec!["A".to_string(), "B".to_string()],
+                },
+            ],
+            db.validate()
+        );
+    }
+
+    #[test]
+    fn absolute_numbering() {
+        let episodes = (1..=12)
+            .filter(|e| *e != 6)
+            .map(|e| (1, e))
+            .chain((1..=3).map(|e| (2, e)))
+            .map(|ep| (Episode::from(ep), Vec::new()))
+            .chain([
+                (Episode::from((0, 1)), Vec::new()),
+                (
+                    Episode::Special {
+                        filename: "OVA.mkv".to_string(),
+                    },
+                    Vec::new(),
+                ),
+            ])
+            .collect();
+        let anime = anime("Show", episodes);
+
+        assert_eq!(Some(1), anime.to_absolute(&Episode::from((1, 1))));
+        assert_eq!(Some(6), anime.to_absolute(&Episode::from((1, 7))));
+        assert_eq!(Some(11), anime.to_absolute(&Episode::from((1, 12))));
+        assert_eq!(Some(12), anime.to_absolute(&Episode::from((2, 1))));
+        assert_eq!(None, anime.to_absolute(&Episode::from((0, 1))));
+        assert_eq!(None, anime.to_absolute(&Episode::from((2, 4))));
+        assert_eq!(Some(Episode::from((2, 3))), anime.from_absolute(14));
+        assert_eq!(Some(Episode::from((1, 1))), anime.from_absolute(1));
+        assert_eq!(None, anime.from_absolute(0));
+        assert_eq!(None, anime.from_absolute(15));
+    }
+
+    #[test]
+    fn watched_semantics() {
+        let episodes = (1..=3)
+            .map(|e| (Episode::from((1, e)), Vec::new()))
+            .collect();
+        let mut anime = anime("Show", episodes);
+        assert_eq!(None, anime.last_finished());
+        assert_eq!(Some(Episode::from((1, 1))), anime.up_next());
+
+        anime.update_watched(Episode::from((1, 1))).unwrap();
+        assert_eq!(Some(Episode::from((1, 1))), anime.last_finished());
+        assert_eq!(Some(Episode::from((1, 2))), anime.up_next());
+
+        anime.update_watched(Episode::from((1, 2))).unwrap();
+        anime.update_watched(Episode::from((1, 3))).unwrap();
+        assert_eq!(Some(Episode::from((1, 3))), anime.last_finished());
+        assert_eq!(Episode::from((1, 3)), anime.current_episode());
+        assert_eq!(None, anime.up_next());
+    }
+
+    #[test]
+    fn mark_watched_up_to() {
+        let episodes = (1..=2)
+            .flat_map(|s| (1..=10).map(move |e| (Episode::from((s, e)), Vec::new())))
+            .collect();
+        let mut anime = anime("Show", episodes);
+
+        anime.mark_watched_up_to(Episode::from((2, 8))).unwrap();
+        assert_eq!(Some(Episode::from((2, 8))), anime.last_finished());
+        assert_eq!(Some(Episode::from((2, 9))), anime.up_next());
+        assert!(anime.is_watched(&Episode::from((1, 1))));
+        assert!(anime.is_watched(&Episode::from((2, 8))));
+        assert!(!anime.is_watched(&Episode::from((2, 9))));
+        assert_eq!(0.9, anime.completion());
+
+        assert!(matches!(
+            anime.mark_watched_up_to(Episode::from((3, 1))),
+            Err(DatabaseError::InvalidEpisode(_))
+        ));
+        assert_eq!(Some(Episode::from((2, 8))), anime.last_finished());
+    }
+
+    #[test]
+    fn error_source() {
+        use std::error::Error;
+
+        let err = DatabaseError::from(File::open("/nonexistent/anime.db").unwrap_err());
+        assert!(err.source().is_some());
+
+        let err = DatabaseError::from(flexbuffers::from_slice::<Database>(&[]).unwrap_err());
+        assert!(err.source().is_some());
+    }
+
+    #[test]
+    fn scan_report_empty() {
+        let root = fixture(
+            "scan-report-empty",
+            &[
+                "Show/Show - 01.mkv",
+                "Not Anime/cover.jpg",
+                "Not Anime/notes.txt",
+            ],
+        );
+        fs::create_dir(root.join("Empty")).unwrap();
+
+        let mut db = empty_db();
+        let report = db.scan_report(vec![root.to_str().unwrap()]);
+        assert_eq!(3, report.anime);
+        assert_eq!(1, report.episodes);
+        assert_eq!(
+            vec!["Empty".to_string(), "Not Anime".to_string()],
+            report.empty
+        );
+    }
+
+    #[test]
+    fn status_summary() {
+        let mut db = empty_db();
+        for (name, status) in [
+            ("A", WatchStatus::Watching),
+            ("B", WatchStatus::Completed),
+            ("C", WatchStatus::Watching),
+            ("D", WatchStatus::PlanToWatch),
+        ] {
+            let mut anime = anime(name, Vec::new());
+            anime.set_status(status);
+            db.anime_map.insert(name.to_string(), anime);
+        }
+
+        assert_eq!(
+            BTreeMap::from([
+                (WatchStatus::PlanToWatch, 1),
+                (WatchStatus::Watching, 2),
+                (WatchStatus::Completed, 1),
+            ]),
+            db.status_summary()
+        );
+    }
+
+    #[test]
+    fn group_special_versions() {
+        let root = fixture(
+            "group-specials",
+            &[
+                "Show/[Group] Show OVA 1 [720p].mkv",
+                "Show/[Group] Show OVA 1 [1080p].mkv",
+                "Show/[Group] Show OVA 2 [1080p].mkv",
+            ],
+        );
+        let anime = Anime::from_path(root.join("Show"), 0);
+        let mut counts = anime
+            .episodes()
+            .iter()
+            .map(|(_, paths)| paths.len())
+            .collect::<Vec<_>>();
+        counts.sort();
+        assert_eq!(vec![1, 2], counts);
+    }
+
+    #[test]
+    fn write_large_database() {
+        let mut db = empty_db();
+        for i in 0..2000 {
+            let name = format!("Anime {i:04}");
+            let episodes = (1..=24)
+                .map(|e| {
+                    let path = format!("/anime/{name}/{name} - {e:02} [1080p].mkv");
+                    (Episode::from((1, e)), vec![path])
+                })
+                .collect();
+            db.anime_map.insert(name.clone(), anime(name, episodes));
+        }
+        let path = std::env::temp_dir().join("anime-database-lib-write-large.db");
+        db.write(&path).unwrap();
+
+        let mut read = Database::new(path.to_str().unwrap(), Vec::<&str>::new()).unwrap();
+        assert_eq!(2000, read.anime_map.len());
+        let anime = read.get_anime("Anime 1999").unwrap();
+        assert_eq!(db.anime_map["Anime 1999"].episodes, anime.episodes);
+    }
+
+    #[test]
+    fn with_episodes_next_episode() {
+        let anime = Anime::with_episodes(
+            "/nonexistent/Show",
+            vec![
+                (Episode::from((1, 2)), vec!["Show - 02.mkv".to_string()]),
+                (Episode::from((1, 1)), vec!["Show - 01.mkv".to_string()]),
+            ],
+        );
+        assert_eq!(Episode::from((1, 1)), anime.episodes()[0].0);
+        assert_eq!(Some(Episode::from((1, 2))), anime.next_episode().unwrap());
+    }
+
+    #[test]
+    fn relative_paths_resolve_after_move() {
+        let root = fixture("relative-paths", &["Show/Season 1/Show - 01.mkv"]);
+        let moved = std::env::temp_dir().join("anime-database-lib-relative-paths-moved");
+        let _ = fs::remove_dir_all(&moved);
+
+        let mut db = empty_db();
+        db.update(vec![root.to_str().unwrap()]);
+        let rel = Path::new("Season 1").join("Show - 01.mkv");
+        let anime = db.get_anime("Show").unwrap();
+        assert_eq!(vec![o_to_str!(rel)], anime.episodes()[0].1);
+        assert_eq!(
+            root.join("Show").join(&rel),
+            anime.resolve_path(&o_to_str!(rel))
+        );
+
+        fs::rename(&root, &moved).unwrap();
+        db.update(vec![moved.to_str().unwrap()]);
+        let anime = db.get_anime("Show").unwrap();
+        assert_eq!(vec![o_to_str!(rel)], anime.episodes()[0].1);
+        let resolved = anime.resolve_path(&anime.episodes()[0].1[0]);
+        assert_eq!(moved.join("Show").join(&rel), resolved);
+        assert!(resolved.exists());
+    }
+
+    #[test]
+    #[cfg(feature = "dedupe")]
+    fn dedupe_by_hash() {
+        let root = fixture("dedupe", &[]);
+        for (file, content) in [
+            ("Show - 01.mkv", "episode one"),
+            ("Show - 01 (copy).mkv", "episode one"),
+            ("Show - 02.mkv", "episode one"),
+            ("Show - 03.mkv", "episode three"),
+        ] {
+            fs::write(root.join(file), content).unwrap();
+        }
+        let mut anime = Anime::from_path(&root, 0);
+        assert_eq!(3, anime.episodes().len());
+
+        assert_eq!(2, anime.dedupe_by_hash());
+        let episodes = anime
+            .episodes()
+            .iter()
+            .map(|(ep, paths)| (ep.clone(), paths.len()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![(Episode::from((1, 1)), 1), (Episode::from((1, 3)), 1)],
+            episodes
+        );
+    }
+
+    #[test]
+    fn combined_season_folder() {
+        let root = fixture(
+            "combined-seasons",
+            &[
+                "Show S1-S2/[Group] Show - S01E11 [1080p].mkv",
+                "Show S1-S2/[Group] Show - S01E12 [1080p].mkv",
+                "Show S1-S2/[Group] Show - S02E01 [1080p].mkv",
+                "Show S1-S2/[Group] Show - S02E02 [1080p].mkv",
+            ],
+        );
+        let mut anime = Anime::from_path(root.join("Show S1-S2"), 0);
+        let seasons = anime.seasons();
+        assert_eq!(vec![&1, &2], seasons.keys().collect::<Vec<_>>());
+        assert_eq!(
+            vec![&Episode::from((2, 1)), &Episode::from((2, 2))],
+            seasons[&2]
+        );
+
+        anime.update_watched(Episode::from((1, 12))).unwrap();
+        assert_eq!(Some(Episode::from((2, 1))), anime.next_episode().unwrap());
+    }
+
+    #[test]
+    fn season_rollover_continued_numbering() {
+        let anime = anime(
+            "Show",
+            vec![
+                (Episode::from((1, 12)), Vec::new()),
+                (Episode::from((2, 14)), Vec::new()),
+                (Episode::from((2, 13)), Vec::new()),
+            ],
+        );
+        assert_eq!(
+            Some(Episode::from((2, 13))),
+            anime.next_episode_raw((1, 12))
+        );
+        assert_eq!(None, anime.next_episode_raw((2, 14)));
+    }
+
+    #[test]
+    fn resume_episode() {
+        let mut anime = anime(
+            "Show",
+            vec![
+                (
+                    Episode::Special {
+                        filename: "Show - NCOP.mkv".to_string(),
+                    },
+                    Vec::new(),
+                ),
+                (Episode::from((1, 1)), Vec::new()),
+                (Episode::from((1, 2)), Vec::new()),
+                (Episode::from((1, 4)), Vec::new()),
+            ],
+        );
+        assert_eq!(Some(Episode::from((1, 1))), anime.resume_episode());
+
+        anime.update_watched(Episode::from((1, 2))).unwrap();
+        assert_eq!(Some(Episode::from((1, 4))), anime.resume_episode());
+
+        anime.update_watched(Episode::from((1, 4))).unwrap();
+        assert_eq!(None, anime.resume_episode());
+    }
+
+    #[test]
+    fn playlist_special_placement() {
+        let ova = Episode::Special {
+            filename: "Show OVA.mkv".to_string(),
+        };
+        let ncop = Episode::Special {
+            filename: "Show NCOP.mkv".to_string(),
+        };
+        let anime = anime(
+            "Show",
+            vec![
+                (Episode::from((1, 1)), Vec::new()),
+                (Episode::from((2, 1)), Vec::new()),
+                (ova.clone(), Vec::new()),
+                (ncop.clone(), Vec::new()),
+            ],
+        );
+        let s1 = Episode::from((1, 1));
+        let s2 = Episode::from((2, 1));
+
+        assert_eq!(vec![&s1, &s2, &ncop, &ova], anime.playlist());
+        assert_eq!(
+            vec![&s1, &ova, &s2, &ncop],
+            anime.playlist_by(|special| (special == &ova).then(|| Episode::from((1, 1))))
+        );
+    }
+
+    #[test]
+    fn reader_writer_round_trip() {
+        let mut db = empty_db();
+        let mut show = anime(
+            "/anime/Show",
+            vec![
+                (Episode::from((1, 1)), vec!["Show - 01.mkv".to_string()]),
+                (Episode::from((1, 2)), vec!["Show - 02.mkv".to_string()]),
+            ],
+        );
+        show.update_watched(Episode::from((1, 2))).unwrap();
+        db.anime_map.insert("Show".to_string(), show);
+
+        let mut buf = std::io::Cursor::new(Vec::new());
+        db.to_writer(&mut buf).unwrap();
+        buf.set_position(0);
+        let mut read = Database::from_reader(buf).unwrap();
+
+        let show = read.get_anime("Show").unwrap();
+        assert_eq!(db.anime_map["Show"].episodes, show.episodes);
+        assert_eq!(Some(Episode::from((1, 2))), show.last_finished());
+    }
+
+    #[test]
+    fn loose_files_as_anime() {
+        let root = fixture(
+            "loose-files",
+            &[
+                "Show/Show - 01.mkv",
+                "Show/Show - 02.mkv",
+                "Movie (2019).mkv",
+            ],
+        );
+        let mut db = empty_db();
+        db.set_scan_config(ScanConfig {
+            loose_files: true,
+            ..ScanConfig::default()
+        });
+        db.update(vec![root.to_str().unwrap()]);
+
+        assert_eq!(2, db.get_anime("Show").unwrap().episodes().len());
+        let movie = db.get_anime("Movie (2019)").unwrap();
+        assert_eq!(1, movie.episodes().len());
+        let path = &movie.episodes()[0].1[0];
+        assert_eq!(root.join("Movie (2019).mkv"), movie.resolve_path(path));
+
+        let mut db = empty_db();
+        db.update(vec![root.to_str().unwrap()]);
+        assert!(db.get_anime("Movie (2019)").is_none());
+    }
+
+    #[test]
+    fn watched_out_of_order() {
+        let episodes = (1..=6)
+            .map(|e| (Episode::from((1, e)), Vec::new()))
+            .chain([(
+                Episode::Special {
+                    filename: "Show OVA.mkv".to_string(),
+                },
+                Vec::new(),
+            )])
+            .collect();
+        let mut anime = anime("Show", episodes);
+        for e in [5, 1, 3] {
+            anime.set_watched(Episode::from((1, e)), true).unwrap();
+        }
+        anime.set_watched(Episode::from((1, 3)), false).unwrap();
+
+        assert!(anime.is_watched(&Episode::from((1, 1))));
+        assert!(!anime.is_watched(&Episode::from((1, 3))));
+        assert!(anime.is_watched(&Episode::from((1, 5))));
+        assert_eq!(2.0 / 6.0, anime.completion());
+        assert_eq!(None, anime.last_finished());
+        assert!(anime.set_watched(Episode::from((1, 7)), true).is_err());
+    }
+
+    #[test]
+    fn anime_names_sorted_and_borrowed() {
+        let mut db = empty_db();
+        for name in ["Yuyushiki", "Vinland Saga", "Girls und Panzer"] {
+            db.anime_map
+                .insert(name.to_string(), anime(name, Vec::new()));
+        }
+        let names = db.anime_names();
+        assert_eq!(vec!["Girls und Panzer", "Vinland Saga", "Yuyushiki"], names);
+        let (key, _) = db.anime_map.first_key_value().unwrap();
+        assert_eq!(key.as_ptr(), names[0].as_ptr());
+    }
+
+    #[test]
+    fn scan_warnings() {
+        let root = fixture("scan-warnings", &["Show/Show - 01.mkv"]);
+        fs::create_dir(root.join("Show/Show - 02.mkv")).unwrap();
+        let anime = Anime::from_path(root.join("Show"), 0);
+        assert_eq!(1, anime.episodes().len());
+        assert_eq!(
+            vec![ScanWarning::NotAFile {
+                path: root.join("Show/Show - 02.mkv"),
+            }],
+            anime.scan_warnings()
+        );
+
+        let missing = Anime::from_path(root.join("Missing"), 0);
+        assert!(matches!(
+            missing.scan_warnings(),
+            [ScanWarning::Unreadable { path, .. }] if path == &root.join("Missing")
+        ));
+    }
+
+    #[test]
+    fn inherit_season_from_context() {
+        let root = fixture(
+            "inherit-season",
+            &[
+                "Show S2/[Group] Show - S02E01 [1080p].mkv",
+                "Show S2/[Group] Show - E02 [1080p].mkv",
+                "Show S2/Season 3/[Group] Show - E01 [1080p].mkv",
+                "Show S2/Mixed/Show - S01E12.mkv",
+                "Show S2/Mixed/Show - S02E03.mkv",
+                "Show S2/Mixed/Show - E04.mkv",
+            ],
+        );
+        let anime = Anime::from_path(root.join("Show S2"), 0);
+        let episodes = anime
+            .episodes()
+            .iter()
+            .map(|(ep, _)| ep.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                Episode::from((1, 4)),
+                Episode::from((1, 12)),
+                Episode::from((2, 1)),
+                Episode::from((2, 2)),
+                Episode::from((2, 3)),
+                Episode::from((3, 1)),
+            ],
+            episodes
+        );
+    }
+
+    #[test]
+    fn inherit_season_from_textual_markers() {
+        let root = fixture(
+            "inherit-textual-season",
+            &[
+                "Show 2nd Season/[Group] Show - 01 [1080p].mkv",
+                "Show II/[Group] Show - 01 [1080p].mkv",
+                "Show/Season 1/Show - 01.mkv",
+            ],
+        );
+        for (dir, season) in [("Show 2nd Season", 2), ("Show II", 2), ("Show", 1)] {
+            let anime = Anime::from_path(root.join(dir), 0);
+            assert_eq!(Episode::from((season, 1)), anime.episodes()[0].0, "{dir}");
+        }
+    }
+
+    #[test]
+    fn anonymized_strips_paths() {
+        let root = fixture(
+            "anonymized",
+            &["Show/Season 1/Show - 01.mkv", "Show/Season 1/Show - 02.mkv"],
+        );
+        let mut db = empty_db();
+        db.update(vec![root.to_str().unwrap()]);
+        let show = db.get_anime("Show").unwrap();
+        show.update_watched(Episode::from((1, 1))).unwrap();
+        show.episodes[0]
+            .1
+            .push(o_to_str!(root.join("Show/Show - 01 (old).mkv")));
+
+        let anonymized = db.anonymized();
+        let mut buf = Vec::new();
+        anonymized.to_writer(&mut buf).unwrap();
+        let root = root.to_str().unwrap().as_bytes();
+        assert!(!buf.windows(root.len()).any(|w| w == root));
+
+        let show = &anonymized.anime_map["Show"];
+        assert_eq!("Show", show.path);
+        assert_eq!(
+            vec![
+                "Show - 01.mkv".to_string(),
+                "Show - 01 (old).mkv".to_string()
+            ],
+            show.episodes[0].1
+        );
+        assert_eq!(Some(Episode::from((1, 1))), show.last_finished());
+        assert!(show.is_watched(&Episode::from((1, 1))));
+    }
+
+    #[test]
+    fn on_watch_callback() {
+        use std::sync::{Arc, Mutex};
+
+        let mut db = empty_db();
+        db.anime_map.insert(
+            "Show".to_string(),
+            anime("Show", vec![((1, 1).into(), Vec::new())]),
+        );
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let log = Arc::clone(&calls);
+        db.set_on_watch(Box::new(move |name, ep| {
+            log.lock().unwrap().push((name.to_string(), ep.clone()))
+        }));
+
+        db.update_watched("Show", Episode::from((1, 1))).unwrap();
+        assert!(db.update_watched("Show", Episode::from((1, 2))).is_err());
+        assert!(db.update_watched("Other", Episode::from((1, 1))).is_err());
+        assert_eq!(
+            vec![("Show".to_string(), Episode::from((1, 1)))],
+            *calls.lock().unwrap()
+        );
+    }
+
+    #[test]
+    fn franchises_cluster_seasons() {
+        let mut db = empty_db();
+        for name in ["Yuru Yuri", "Yuru Yuri S2", "Non Non Biyori"] {
+            db.anime_map
+                .insert(name.to_string(), anime(name, Vec::new()));
+        }
+        let franchises = db.franchises();
+        assert_eq!(2, franchises.len());
+        assert_eq!(vec!["Yuru Yuri", "Yuru Yuri S2"], franchises["Yuru Yuri"]);
+        assert_eq!(vec!["Non Non Biyori"], franchises["Non Non Biyori"]);
+    }
+
+    #[test]
+    fn episode_path_lookup() {
+        let mut db = empty_db();
+        db.anime_map.insert(
+            "Show".to_string(),
+            anime(
+                "Show",
+                vec![(
+                    (1, 1).into(),
+                    vec!["Show - 01.mkv".to_string(), "Show - 01v2.mkv".to_string()],
+                )],
+            ),
+        );
+        let ep = Episode::from((1, 1));
+        assert_eq!(Some("Show - 01.mkv"), db.episode_path("Show", &ep));
+        assert_eq!(None, db.episode_path("Other", &ep));
+        assert_eq!(None, db.episode_path("Show", &Episode::from((1, 2))));
+
+        db.set_scan_config(ScanConfig {
+            normalize_keys: true,
+            ..ScanConfig::default()
+        });
+        db.anime_map.insert(
+            "k-on!".to_string(),
+            anime(
+                "K-On!",
+                vec![((1, 1).into(), vec!["K-On! - 01.mkv".to_string()])],
+            ),
+        );
+        assert_eq!(Some("K-On! - 01.mkv"), db.episode_path("K-ON!", &ep));
+    }
+
+    #[test]
+    fn open_readonly() {
+        let root = fixture("readonly", &["Show/Show - 01.mkv"]);
+        let db_path = root.join("anime.db");
+        let mut db = empty_db();
+        db.update(vec![root.to_str().unwrap()]);
+        db.write(&db_path).unwrap();
+        let stored = fs::read(&db_path).unwrap();
+
+        let db = Database::open_readonly(&db_path).unwrap();
+        assert_eq!(vec!["Show"], db.anime_names());
+        let show = db.get_anime("Show").unwrap();
+        assert_eq!(
+            Some("Show - 01.mkv"),
+            db.episode_path("Show", &(1, 1).into())
+        );
+        assert_eq!(None, show.last_finished());
+        drop(db);
+        assert_eq!(stored, fs::read(&db_path).unwrap());
+
+        let missing = root.join("missing.db");
+        assert!(Database::open_readonly(&missing).is_err());
+        assert!(!missing.exists());
+    }
+
+    #[test]
+    fn shared_database_threads() {
+        let mut db = empty_db();
+        db.anime_map.insert(
+            "Show".to_string(),
+            anime("Show", vec![((1, 1).into(), Vec::new())]),
+        );
+        let shared = SharedDatabase::new(db);
+
+        let readers = (0..4)
+            .map(|_| {
+                let shared = shared.clone();
+                std::thread::spawn(move || {
+                    for _ in 0..100 {
+                        assert_eq!(vec!["Show"], shared.read().anime_names());
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        let writer = {
+            let shared = shared.clone();
+            std::thread::spawn(move || {
+                shared
+                    .write()
+                    .update_watched("Show", Episode::from((1, 1)))
+                    .unwrap()
+            })
+        };
+        for reader in readers {
+            reader.join().unwrap();
+        }
+        writer.join().unwrap();
+
+        let db = shared.read();
+        assert!(db.anime("Show").unwrap().is_watched(&Episode::from((1, 1))));
+    }
+
+    #[test]
+    fn missing_episodes() {
+        let episodes = [(1, 1), (1, 2), (1, 4), (2, 1), (2, 3)]
+            .into_iter()
+            .map(|ep| (Episode::from(ep), Vec::new()))
+            .collect();
+        let gapped = anime("Show", episodes);
+        assert_eq!(
+            vec![Episode::from((1, 3)), Episode::from((2, 2))],
+            gapped.missing_episodes()
+        );
+
+        let episodes = [(1, 1), (1, 2), (1, 3)]
+            .into_iter()
+            .map(|ep| (Episode::from(ep), Vec::new()))
+            .collect();
+        let complete = anime("Show", episodes);
+        assert!(complete.missing_episodes().is_empty());
+    }
+
+    #[test]
+    fn get_anime_fuzzy() {
+        let mut db = empty_db();
+        for name in ["Yuru Yuri", "[Group] Non Non Biyori", "Non_Non_Biyori"] {
+            db.anime_map
+                .insert(name.to_string(), anime(name, Vec::new()));
+        }
+        let path = |anime: Option<&mut Anime>| anime.map(|anime| anime.path.clone());
+        assert_eq!(
+            Some("Non_Non_Biyori".to_string()),
+            path(db.get_anime_fuzzy("Non_Non_Biyori"))
+        );
+        assert_eq!(
+            Some("Yuru Yuri".to_string()),
+            path(db.get_anime_fuzzy("yuru.yuri"))
+        );
+        assert_eq!(None, path(db.get_anime_fuzzy("non non biyori")));
+        assert_eq!(None, path(db.get_anime_fuzzy("Lucky Star")));
+    }
+
+    #[test]
+    fn export_episodes_jsonl() {
+        let mut db = empty_db();
+        let episodes = vec![
+            ((1, 1).into(), vec!["Show - 01.mkv".to_string()]),
+            ((1, 2).into(), vec!["Show - 02.mkv".to_string()]),
+            (
+                Episode::Special {
+                    filename: "OVA.mkv".to_string(),
+                },
+                vec!["OVA.mkv".to_string()],
+            ),
+        ];
+        db.anime_map
+            .insert("Show".to_string(), anime("Show", episodes));
+
+        let mut buf = Vec::new();
+        db.export_episodes_jsonl(&mut buf).unwrap();
+        let output = String::from_utf8(buf).unwrap();
+        let lines = output.lines().collect::<Vec<_>>();
+        assert_eq!(3, lines.len());
+        assert!(lines.contains(
+            &r#"{"anime":"Show","season":1,"episode":2,"special":null,"path":"Show - 02.mkv"}"#
+        ));
+        assert!(lines.contains(
+            &r#"{"anime":"Show","season":null,"episode":null,"special":"OVA.mkv","path":"OVA.mkv"}"#
+        ));
+    }
+
+    #[test]
+    fn animes_tie_break_by_name() {
+        let mut db = empty_db();
+        for name in ["Yuru Yuri", "K-On!", "Non Non Biyori"] {
+            db.anime_map
+                .insert(name.to_string(), anime(name, Vec::new()));
+        }
+        db.anime_map.get_mut("Non Non Biyori").unwrap().last_watched = 10;
+        let names = db
+            .animes()
+            .unwrap()
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(vec!["Non Non Biyori", "K-On!", "Yuru Yuri"], names);
+    }
+
+    #[test]
+    fn episode_and_special_counts() {
+        let episodes = vec![
+            ((1, 1).into(), Vec::new()),
+            ((1, 2).into(), Vec::new()),
+            (
+                Episode::Special {
+                    filename: "OVA.mkv".to_string(),
+                },
+                Vec::new(),
+            ),
+        ];
+        let anime = anime("Show", episodes);
+        assert_eq!(2, anime.episode_count());
+        assert_eq!(1, anime.special_count());
+    }
+
+    #[test]
+    fn with_new_episodes() {
+        let mut db = empty_db();
+        for name in ["Airing", "Caught Up", "Unstarted"] {
+            let episodes = [(1, 1), (1, 2), (1, 3)]
+                .into_iter()
+                .map(|ep| (Episode::from(ep), Vec::new()))
+                .collect();
+            db.anime_map.insert(name.to_string(), anime(name, episodes));
+        }
+        db.update_watched("Airing", Episode::from((1, 1))).unwrap();
+        db.update_watched("Caught Up", Episode::from((1, 3)))
+            .unwrap();
+
+        let airing = "Airing".to_string();
+        assert_eq!(vec![(&airing, 2)], db.with_new_episodes());
+    }
+
+    #[test]
+    fn from_reader_ignores_unknown_fields() {
+        #[derive(Serialize)]
+        struct NewerAnime<'a> {
+            #[serde(flatten)]
+            anime: &'a Anime,
+            rating: u8,
+        }
+        #[derive(Serialize)]
+        struct NewerDatabase<'a> {
+            anime_map: BTreeMap<&'a str, NewerAnime<'a>>,
+            version: u32,
+        }
+
+        let show = anime(
+            "Show",
+            vec![((1, 1).into(), vec!["Show - 01.mkv".to_string()])],
+        );
+        let newer = NewerDatabase {
+            anime_map: BTreeMap::from([(
+                "Show",
+                NewerAnime {
+                    anime: &show,
+                    rating: 9,
+                },
+            )]),
+            version: 2,
+        };
+        let buf = flexbuffers::to_vec(&newer).unwrap();
+
+        let db = Database::from_reader(buf.as_slice()).unwrap();
+        assert_eq!(
+            Some("Show - 01.mkv"),
+            db.episode_path("Show", &(1, 1).into())
+        );
+    }
+
+    #[test]
+    fn search_episodes_by_title() {
+        let root = fixture(
+            "search-episodes",
+            &[
+                "Show/Show - 01 - The Beginning [1080p].mkv",
+                "Show/Show - 02 - An Uneventful Good Life [1080p].mkv",
+                "Other/Other - 01 - Another Good Day.mkv",
+                "Other/Other - 02.mkv",
+            ],
+        );
+        let mut db = empty_db();
+        db.update(vec![root.to_str().unwrap()]);
+
+        let show = "Show".to_string();
+        assert_eq!(
+            vec![(&show, &Episode::from((1, 2)))],
+            db.search_episodes("uneventful good")
+        );
+        assert_eq!(2, db.search_episodes("GOOD").len());
+        assert!(db.search_episodes("missing").is_empty());
+    }
+
+    #[test]
+    fn anime_display() {
+        let episodes = (1..=24)
+            .map(|episode| (Episode::from((1, episode)), Vec::new()))
+            .collect();
+        let mut show = anime("/anime/Show", episodes);
+        assert_eq!("Show: - / 24 (Plan to Watch)", show.to_string());
+
+        show.update_watched(Episode::from((1, 5))).unwrap();
+        show.set_status(WatchStatus::Watching);
+        assert_eq!("Show: S01E05 / 24 (Watching)", show.to_string());
+    }
+
+    #[test]
+    fn needs_update() {
+        let root = fixture(
+            "needs-update",
+            &["Show/Show - 01.mkv", "Other/Other - 01.mkv"],
+        );
+        let dirs = vec![root.to_str().unwrap()];
+        let mut db = empty_db();
+        assert!(db.needs_update(dirs.clone()));
+        db.update(dirs.clone());
+        assert!(!db.needs_update(dirs.clone()));
+
+        // Directory times have a resolution of one second.
+        std::thread::sleep(std::time::Duration::from_millis(1100));
+        fs::write(root.join("Show/Show - 02.mkv"), "").unwrap();
+        assert!(db.needs_update(dirs.clone()));
+        db.update(dirs.clone());
+        assert!(!db.needs_update(dirs.clone()));
+
+        fs::remove_dir_all(root.join("Other")).unwrap();
+        assert!(db.needs_update(dirs));
+    }
+
+    #[test]
+    fn reparse_specials_with_custom_parser() {
+        let special = |filename: &str| {
+            (
+                Episode::Special {
+                    filename: filename.to_string(),
+                },
+                vec![filename.to_string()],
+            )
+        };
+        let episodes = vec![
+            ((1, 1).into(), vec!["Show #1.mkv".to_string()]),
+            special("Show #1 v2.mkv"),
+            special("Show #2.mkv"),
+            special("Show Special.mkv"),
+        ];
+        let mut show = anime("Show", episodes);
+        let parser = EpisodeParser::with_pattern(r"#(?P<e>\d+)").unwrap();
+        assert_eq!(2, show.reparse_specials(&parser));
+        assert_eq!(
+            vec![
+                special("Show Special.mkv"),
+                (
+                    (1, 1).into(),
+                    vec!["Show #1.mkv".to_string(), "Show #1 v2.mkv".to_string()]
+                ),
+                ((1, 2).into(), vec!["Show #2.mkv".to_string()]),
+            ],
+            *show.episodes()
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn scan_non_utf8_filenames() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        let root = fixture("non-utf8", &[]);
+        let dir = root.join("Show");
+        fs::create_dir_all(&dir).unwrap();
+        let filename = OsStr::from_bytes(b"Show - 01 \xff.mkv");
+        if File::create(dir.join(filename)).is_err() {
+            // The file system only allows UTF-8 names.
+            return;
+        }
+
+        let anime = Anime::from_path(&dir, 0);
+        assert_eq!(
+            vec![(
+                Episode::from((1, 1)),
+                vec!["Show - 01 \u{FFFD}.mkv".to_string()]
+            )],
+            *anime.episodes()
+        );
+    }
+
+    #[test]
+    fn next_in_queue() {
+        let mut db = empty_db();
+        let episodes = vec![
+            ((1, 1).into(), vec!["Show - 01.mkv".to_string()]),
+            (
+                Episode::Special {
+                    filename: "OVA.mkv".to_string(),
+                },
+                vec!["OVA.mkv".to_string()],
+            ),
+            ((2, 1).into(), vec!["Season 2/Show - 01.mkv".to_string()]),
+        ];
+        db.anime_map
+            .insert("Show".to_string(), anime("/anime/Show", episodes));
+
+        assert_eq!(
+            Some((
+                "Show".to_string(),
+                Episode::from((2, 1)),
+                o_to_str!(Path::new("/anime/Show/Season 2/Show - 01.mkv"))
+            )),
+            db.next_in_queue(("Show", &Episode::from((1, 1))))
+        );
+        assert_eq!(None, db.next_in_queue(("Show", &Episode::from((2, 1)))));
+        assert_eq!(None, db.next_in_queue(("Other", &Episode::from((1, 1)))));
+    }
+
+    #[test]
+    fn extra_metadata_round_trip() {
+        let mut db = empty_db();
+        let mut show = anime("Show", Vec::new());
+        show.set_extra("anilist_id", "12345");
+        show.set_extra("notes", "Rewatch with friends");
+        show.set_extra("notes", "Rewatch");
+        db.anime_map.insert("Show".to_string(), show);
+
+        let mut buf = Vec::new();
+        db.to_writer(&mut buf).unwrap();
+        let db = Database::from_reader(buf.as_slice()).unwrap();
+        let show = db.anime("Show").unwrap();
+        assert_eq!(Some("12345"), show.get_extra("anilist_id"));
+        assert_eq!(Some("Rewatch"), show.get_extra("notes"));
+        assert_eq!(None, show.get_extra("mal_id"));
+    }
+
+    #[test]
+    fn best_path_for() {
+        let paths = [
+            "[Group] Show - 01 [720p].mkv",
+            "[Other] Show - 01 [1080p].mkv",
+            "[Third] Show - 01 (1920x1080).mkv",
+            "Show - 01.mkv",
+        ]
+        .map(String::from);
+        let show = anime("Show", vec![((1, 1).into(), paths.to_vec())]);
+        assert_eq!(
+            Some("[Other] Show - 01 [1080p].mkv"),
+            show.best_path_for(&Episode::from((1, 1)))
+        );
+        assert_eq!(None, show.best_path_for(&Episode::from((1, 2))));
+    }
+
+    #[test]
+    fn insert_episode_keeps_order() {
+        let mut show = anime("Show", Vec::new());
+        show.insert_episode(Episode::from((1, 3)), "Show - 03.mkv".to_string());
+        show.insert_episode(Episode::from((1, 1)), "Show - 01.mkv".to_string());
+        show.insert_episode(
+            Episode::Special {
+                filename: "OVA.mkv".to_string(),
+            },
+            "OVA.mkv".to_string(),
+        );
+        show.insert_episode(Episode::from((2, 1)), "Show S02E01.mkv".to_string());
+        show.insert_episode(Episode::from((1, 2)), "Show - 02.mkv".to_string());
+        show.insert_episode(Episode::from((1, 2)), "Show - 02v2.mkv".to_string());
+
+        let mut sorted = show.episodes().clone();
+        sorted.sort_by(|(a, _), (b, _)| a.cmp(b));
+        assert_eq!(sorted, *show.episodes());
+        assert_eq!(5, show.episodes().len());
+        assert_eq!(
+            Some(&vec![
+                "Show - 02.mkv".to_string(),
+                "Show - 02v2.mkv".to_string()
+            ]),
+            show.episodes()
+                .iter()
+                .find(|(ep, _)| *ep == Episode::from((1, 2)))
+                .map(|(_, paths)| paths)
+        );
+    }
+
+    #[test]
+    fn save_to_opened_path() {
+        let root = fixture("save", &["Show/Show - 01.mkv"]);
+        let db_path = root.join("anime.db");
+        let mut db =
+            Database::new(db_path.to_str().unwrap(), vec![root.to_str().unwrap()]).unwrap();
+        assert_eq!(Some(db_path.as_path()), db.path());
+        assert!(!db_path.exists());
+        db.save().unwrap();
+
+        let mut db = Database::new(db_path.to_str().unwrap(), Vec::<&str>::new()).unwrap();
+        assert_eq!(vec!["Show"], db.anime_names());
+        db.get_anime("Show")
+            .unwrap()
+            .update_watched(Episode::from((1, 1)))
+            .unwrap();
+        db.save().unwrap();
+        let db = Database::from_reader(File::open(&db_path).unwrap()).unwrap();
+        assert!(db.anime("Show").unwrap().is_watched(&Episode::from((1, 1))));
+
+        assert!(matches!(empty_db().save(), Err(DatabaseError::NoPath)));
+    }
+
+    #[test]
+    fn anchor_special_between_seasons() {
+        let ova = Episode::Special {
+            filename: "Show OVA.mkv".to_string(),
+        };
+        let nced = Episode::Special {
+            filename: "NCED.mkv".to_string(),
+        };
+        let episodes = [(1, 1), (1, 2), (2, 1)]
+            .into_iter()
+            .map(Episode::from)
+            .chain([ova.clone(), nced.clone()])
+            .map(|ep| (ep, Vec::new()))
+            .collect();
+        let mut show = anime("Show", episodes);
+        show.anchor_special("Show OVA.mkv", Episode::from((1, 2)))
+            .unwrap();
+        assert!(show
+            .anchor_special("Missing.mkv", Episode::from((1, 2)))
+            .is_err());
+        assert!(show
+            .anchor_special("NCED.mkv", Episode::from((3, 1)))
+            .is_err());
+
+        let (s1e1, s1e2, s2e1) = (
+            Episode::from((1, 1)),
+            Episode::from((1, 2)),
+            Episode::from((2, 1)),
+        );
+        assert_eq!(vec![&s1e1, &s1e2, &ova, &s2e1, &nced], show.playlist());
+        let seasons = show.seasons();
+        assert_eq!(vec![&s1e1, &s1e2, &ova], seasons[&1]);
+        assert_eq!(vec![&s2e1], seasons[&2]);
+    }
+
+    #[test]
+    fn episodes_in_season() {
+        let ova = Episode::Special {
+            filename: "OVA.mkv".to_string(),
+        };
+        let episodes = vec![
+            ((2, 1).into(), vec!["S02E01.mkv".to_string()]),
+            ((1, 2).into(), vec!["S01E02.mkv".to_string()]),
+            ((1, 1).into(), vec!["S01E01.mkv".to_string()]),
+            ((0, 1).into(), vec!["S00E01.mkv".to_string()]),
+            (ova.clone(), vec!["OVA.mkv".to_string()]),
+        ];
+        let show = anime("Show", episodes);
+        let (s1e1, s1e2) = (Episode::from((1, 1)), Episode::from((1, 2)));
+        assert_eq!(
+            vec![
+                (&s1e1, ["S01E01.mkv".to_string()].as_slice()),
+                (&s1e2, ["S01E02.mkv".to_string()].as_slice()),
+            ],
+            show.episodes_in_season(1)
+        );
+        let season_0 = show
+            .episodes_in_season(0)
+            .into_iter()
+            .map(|(ep, _)| ep)
+            .collect::<Vec<_>>();
+        assert_eq!(vec![&ova, &Episode::from((0, 1))], season_0);
+        assert!(show.episodes_in_season(3).is_empty());
+    }
+
+    #[test]
+    fn season_counts() {
+        let root = fixture(
+            "season-counts",
+            &[
+                "Single/Single - 01.mkv",
+                "Single/Single - 02.mkv",
+                "Single/Single - OVA.mkv",
+                "Multi/Multi - S01E01.mkv",
+                "Multi/Multi - S02E01.mkv",
+                "Multi/Multi - S03E01.mkv",
+                "Multi/Multi - S00E01.mkv",
+            ],
+        );
+        let single = Anime::from_path(root.join("Single"), 0);
+        assert_eq!(1, single.season_count());
+        assert!(!single.is_multi_season());
+
+        let multi = Anime::from_path(root.join("Multi"), 0);
+        assert_eq!(3, multi.season_count());
+        assert!(multi.is_multi_season());
+
+        let empty = anime("/nonexistent/Empty", Vec::new());
+        assert_eq!(0, empty.season_count());
+        assert!(!empty.is_multi_season());
+    }
+
+    #[test]
+    fn format_episode() {
+        let episodes = vec![
+            ((1, 1).into(), Vec::new()),
+            ((1, 5).into(), Vec::new()),
+            ((0, 1).into(), Vec::new()),
+        ];
+        let mut single = anime("Show", episodes);
+        assert_eq!("E05", single.format_episode(&Episode::from((1, 5))));
+        assert_eq!("S00 E01", single.format_episode(&Episode::from((0, 1))));
+        single.set_offset(1);
+        assert_eq!("E06", single.format_episode(&Episode::from((1, 5))));
+
+        let episodes = vec![((1, 5).into(), Vec::new()), ((2, 1).into(), Vec::new())];
+        let multi = anime("Show", episodes);
+        assert_eq!("S01 E05", multi.format_episode(&Episode::from((1, 5))));
+        assert_eq!(
+            "OVA.mkv",
+            multi.format_episode(&Episode::Special {
+                filename: "OVA.mkv".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn import_mal_xml() {
+        let root = fixture("import-mal", &[]);
+        let xml = root.join("animelist.xml");
+        fs::write(
+            &xml,
+            r#"<?xml version="1.0" encoding="UTF-8" ?>
+<myanimelist>
+    <myinfo><user_name>user</user_name></myinfo>
+    <anime>
+        <series_animedb_id>10495</series_animedb_id>
+        <series_title><![CDATA[Yuru Yuri]]></series_title>
+        <my_watched_episodes>3</my_watched_episodes>
+        <my_score>8</my_score>
+        <my_status>Watching</my_status>
+    </anime>
+    <anime>
+        <series_title>K-On! &amp; Friends</series_title>
+        <my_watched_episodes>0</my_watched_episodes>
+        <my_score>0</my_score>
+        <my_status>Plan to Watch</my_status>
+    </anime>
+</myanimelist>"#,
+        )
+        .unwrap();
+
+        let mut db = empty_db();
+        let episodes = (1..=12)
+            .map(|episode| (Episode::from((1, episode)), Vec::new()))
+            .collect();
+        db.anime_map.insert(
+            "[Group] Yuru_Yuri".to_string(),
+            anime("/anime/[Group] Yuru_Yuri", episodes),
+        );
+
+        let report = db.import_mal_xml(&xml).unwrap();
+        assert_eq!(
+            ImportReport {
+                matched: vec!["[Group] Yuru_Yuri".to_string()],
+                unmatched: vec!["K-On! & Friends".to_string()],
+            },
+            report
+        );
+        let show = db.anime("[Group] Yuru_Yuri").unwrap();
+        assert_eq!(WatchStatus::Watching, show.status());
+        assert_eq!(Some(8), show.score());
+        assert_eq!(Some(Episode::from((1, 3))), show.last_finished());
+        assert!(show.is_watched(&Episode::from((1, 2))));
+        assert!(!show.is_watched(&Episode::from((1, 4))));
+
+        fs::write(
+            &xml,
+            r#"<myanimelist>
+    <anime>
+        <series_title>Yuru Yuri</series_title>
+        <my_watched_episodes>3</my_watched_episodes>
+        <my_score>0</my_score>
+    </anime>
+</myanimelist>"#,
+        )
+        .unwrap();
+        db.import_mal_xml(&xml).unwrap();
+        assert_eq!(Some(8), db.anime("[Group] Yuru_Yuri").unwrap().score());
+
+        fs::write(&xml, "not xml").unwrap();
+        assert!(matches!(
+            db.import_mal_xml(&xml),
+            Err(DatabaseError::InvalidImport)
+        ));
+    }
+
+    #[test]
+    fn all_episodes_order() {
+        let mut db = empty_db();
+        let ova = Episode::Special {
+            filename: "OVA.mkv".to_string(),
+        };
+        let episodes = vec![
+            ((2, 1).into(), Vec::new()),
+            ((1, 2).into(), Vec::new()),
+            (ova.clone(), Vec::new()),
+            ((1, 1).into(), Vec::new()),
+        ];
+        db.anime_map
+            .insert("Yuru Yuri".to_string(), anime("Yuru Yuri", episodes));
+        let episodes = vec![((1, 2).into(), Vec::new()), ((1, 1).into(), Vec::new())];
+        db.anime_map
+            .insert("K-On!".to_string(), anime("K-On!", episodes));
+
+        let (k_on, yuru_yuri) = ("K-On!".to_string(), "Yuru Yuri".to_string());
+        assert_eq!(
+            vec![
+                (&k_on, &Episode::from((1, 1))),
+                (&k_on, &Episode::from((1, 2))),
+                (&yuru_yuri, &ova),
+                (&yuru_yuri, &Episode::from((1, 1))),
+                (&yuru_yuri, &Episode::from((1, 2))),
+                (&yuru_yuri, &Episode::from((2, 1))),
+            ],
+            db.all_episodes().collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn repair_keys() {
+        let mut db = empty_db();
+        let mut renamed = anime(
+            "/anime/Show",
+            vec![
+                ((1, 1).into(), vec!["Show - 01.mkv".to_string()]),
+                ((1, 2).into(), vec!["Show - 02.mkv".to_string()]),
+            ],
+        );
+        renamed.update_watched(Episode::from((1, 2))).unwrap();
+        db.anime_map.insert("Old Show".to_string(), renamed);
+        db.anime_map.insert(
+            "Other Old".to_string(),
+            anime("/anime/Other", vec![((1, 1).into(), Vec::new())]),
+        );
+        db.anime_map.insert(
+            "Other".to_string(),
+            anime("/anime/Other", vec![((1, 2).into(), Vec::new())]),
+        );
+
+        assert_eq!(2, db.repair_keys());
+        assert_eq!(vec!["Other", "Show"], db.anime_names());
+        let show = db.anime("Show").unwrap();
+        assert_eq!(Some(Episode::from((1, 2))), show.last_finished());
+        assert_eq!(2, db.anime("Other").unwrap().episode_count());
+        assert_eq!(0, db.repair_keys());
+    }
+
+    #[test]
+    fn scan_errors() {
+        let root = fixture(
+            "scan-errors",
+            &[
+                "Show/Show - 01.mkv",
+                "Show/Trailer.mkv",
+                "Empty/notes.txt",
+                "Other/Other - 01.mkv",
+                "Other/Other - 02.mkv",
+            ],
+        );
+        fs::create_dir_all(root.join("Show/Show - 02.mkv")).unwrap();
+        let missing = root.join("missing");
+
+        let mut db = empty_db();
+        db.update(vec![root.to_str().unwrap(), missing.to_str().unwrap()]);
+        db.update_watched("Other", Episode::from((1, 2))).unwrap();
+        db.get_anime("Other").unwrap().last_updated = 0;
+        fs::remove_file(root.join("Other/Other - 02.mkv")).unwrap();
+        db.update(vec![root.to_str().unwrap(), missing.to_str().unwrap()]);
+        let errors = db.scan_errors();
+        assert_eq!(1, errors.unreadable_dirs.len());
+        assert_eq!(missing, errors.unreadable_dirs[0].0);
+        assert_eq!(
+            vec![(
+                root.join("Show/Show - 02.mkv"),
+                "Not a regular file".to_string()
+            )],
+            errors.unreadable_files
+        );
+        assert_eq!(vec![root.join("Show/Trailer.mkv")], errors.unparsed);
+        assert_eq!(vec!["Empty".to_string()], errors.empty);
+        assert_eq!(
+            vec![(
+                "Other".to_string(),
+                Episode::from((1, 2)),
+                Some(Episode::from((1, 1)))
+            )],
+            errors.moved_progress
+        );
+    }
+
+    #[test]
+    fn extras_dirs_are_specials() {
+        let root = fixture(
+            "extras-dirs",
+            &[
+                "Show/Show - 01.mkv",
+                "Show/Show - 02.mkv",
+                "Show/Extras/Show - 01 Interview.mkv",
+                "Show/extras/Making Of/Show - E03.mkv",
+            ],
+        );
+        let mut db = empty_db();
+        db.set_scan_config(ScanConfig {
+            extras_dirs: vec!["Extras".to_string()],
+            ..ScanConfig::default()
+        });
+        db.update(vec![root.to_str().unwrap()]);
+        let show = db.anime("Show").unwrap();
+        assert_eq!(2, show.episode_count());
+        assert_eq!(2, show.special_count());
+        assert!(show.episodes().contains(&(
+            Episode::Special {
+                filename: "Show - 01 Interview.mkv".to_string()
+            },
+            vec![o_to_str!(Path::new("Extras/Show - 01 Interview.mkv"))]
+        )));
+    }
+
+    #[test]
+    fn total_paths_and_files() {
+        let root = fixture(
+            "total-files",
+            &[
+                "Show/[Group] Show - 01 [1080p].mkv",
+                "Show/[Other] Show - 01 [720p].mkv",
+                "Show/[Group] Show - 02 [1080p].mkv",
+                "Other/Other - 01.mkv",
+            ],
+        );
+        let mut db = empty_db();
+        db.update(vec![root.to_str().unwrap()]);
+        let show = db.anime("Show").unwrap();
+        assert_eq!(2, show.episode_count());
+        assert_eq!(3, show.total_paths());
+        assert_eq!(4, db.total_files());
+    }
+
+    #[test]
+    fn duplicate_paths_sorted() {
+        let root = fixture(
+            "duplicate-paths-sorted",
+            &[
+                "Show/[B] Show - 01 [720p].mkv",
+                "Show/[C] Show - 01.mkv",
+                "Show/[Z] Show - 01 [1080p].mkv",
+                "Show/[A] Show - 01 [720p].mkv",
+                "Show/[Y] Show - 01 [1080p].mkv",
+            ],
+        );
+        let mut show = Anime::from_path(root.join("Show"), 0);
+        show.update_episodes();
+        assert_eq!(
+            show.episodes[0].1,
+            [
+                "[Y] Show - 01 [1080p].mkv",
+                "[Z] Show - 01 [1080p].mkv",
+                "[A] Show - 01 [720p].mkv",
+                "[B] Show - 01 [720p].mkv",
+                "[C] Show - 01.mkv",
+            ]
+        );
+        assert_eq!(
+            show.best_path_for(&Episode::from((1, 1))),
+            Some("[Y] Show - 01 [1080p].mkv")
+        );
+    }
+
+    #[test]
+    fn inserted_and_merged_paths_sorted() {
+        let mut show = anime("/nonexistent/Show", Vec::new());
+        show.insert_episode(
+            Episode::from((1, 1)),
+            "[B] Show - 01 [720p].mkv".to_string(),
+        );
+        show.insert_episode(
+            Episode::from((1, 1)),
+            "[A] Show - 01 [720p].mkv".to_string(),
+        );
+        let mut other = anime("/nonexistent/Show", Vec::new());
+        other.insert_episode(
+            Episode::from((1, 1)),
+            "[C] Show - 01 [1080p].mkv".to_string(),
+        );
+        show.merge(other);
+        assert_eq!(
+            show.episodes[0].1,
+            [
+                "[C] Show - 01 [1080p].mkv",
+                "[A] Show - 01 [720p].mkv",
+                "[B] Show - 01 [720p].mkv",
+            ]
+        );
+    }
+
+    #[test]
+    fn current_episode_fixed_after_deletion() {
+        let root = fixture(
+            "current-episode-deleted",
+            &[
+                "Show/Show - S01E01.mkv",
+                "Show/Show - S01E02.mkv",
+                "Show/Show - S02E01.mkv",
+                "Show/Show - S02E02.mkv",
+            ],
+        );
+        let mut show = Anime::from_path(root.join("Show"), 0);
+        show.update_watched(Episode::from((2, 2))).unwrap();
+
+        fs::remove_file(root.join("Show/Show - S02E01.mkv")).unwrap();
+        fs::remove_file(root.join("Show/Show - S02E02.mkv")).unwrap();
+        show.update_episodes();
+        assert_eq!(Some(Episode::from((1, 2))), show.last_finished());
+        assert_eq!(
+            vec![ScanWarning::CurrentEpisodeMissing {
+                episode: Episode::from((2, 2)),
+                moved_to: Some(Episode::from((1, 2))),
+            }],
+            show.scan_warnings()
+        );
+        assert_eq!(None, show.next_episode().unwrap());
+
+        fs::remove_file(root.join("Show/Show - S01E01.mkv")).unwrap();
+        fs::remove_file(root.join("Show/Show - S01E02.mkv")).unwrap();
+        show.update_episodes();
+        assert_eq!(None, show.last_finished());
+    }
+
+    #[test]
+    #[cfg(feature = "glob")]
+    fn glob_directories() {
+        let root = fixture(
+            "glob",
+            &[
+                "a/anime/Show A/Show A - 01.mkv",
+                "b/anime/Show B/Show B - 01.mkv",
+                "c/movies/Movie/Movie.mkv",
+                "[Anime]/Show C/Show C - 01.mkv",
+            ],
+        );
+        let root = o_to_str!(root);
+        let dirs = Database::expand_directories(&[format!("{root}/*/anime")]).unwrap();
+        assert_eq!(
+            vec![format!("{root}/a/anime"), format!("{root}/b/anime")],
+            dirs
+        );
+
+        let mut db = Database::new(format!("{root}/db"), vec![format!("{root}/*/anime")]).unwrap();
+        assert_eq!(vec!["Show A", "Show B"], db.anime_names());
+
+        assert_eq!(
+            vec![format!("{root}/[Anime]")],
+            Database::expand_directories(&[format!("{root}/[Anime]")]).unwrap()
+        );
+
+        assert!(matches!(
+            Database::expand_directories(&[format!("{root}/[a")]),
+            Err(DatabaseError::Pattern(_))
+        ));
+        db.save().unwrap();
+        let db = Database::new(format!("{root}/db"), vec![format!("{root}/[a")]).unwrap();
+        assert_eq!(vec!["Show A", "Show B"], db.anime_names());
+    }
+
+    #[test]
+    fn backlog_lists_unstarted_anime() {
+        let mut db = empty_db();
+        for (name, date_added, last_watched) in
+            [("Started", 100, 50), ("New", 300, 0), ("Old", 200, 0)]
+        {
+            let mut anime = anime(format!("/nonexistent/{name}"), Vec::new());
+            anime.date_added = date_added;
+            anime.last_watched = last_watched;
+            db.anime_map.insert(name.to_string(), anime);
+        }
+        let names = db
+            .backlog()
+            .into_iter()
+            .map(|(name, _)| name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(vec!["Old", "New"], names);
+    }
+
+    fn watched_anime(episodes: u32) -> Anime {
+        let map = (1..=episodes)
+            .map(|e| (Episode::from((1, e)), vec![format!("Show - {e:02}.mkv")]))
+            .collect();
+        let mut anime = anime("/nonexistent/Show", map);
+        anime
+            .mark_watched_up_to(Episode::from((1, episodes)))
+            .unwrap();
+        anime
+    }
+
+    #[test]
+    fn total_watch_time_known_durations() {
+        let mut db = empty_db();
+        let mut show = watched_anime(2);
+        show.set_duration(Episode::from((1, 1)), Duration::from_secs(1440))
+            .unwrap();
+        show.set_duration(Episode::from((1, 2)), Duration::from_secs(1500))
+            .unwrap();
+        assert!(show
+            .set_duration(Episode::from((1, 3)), Duration::from_secs(1500))
+            .is_err());
+        db.anime_map.insert("Show".to_string(), show);
+        assert_eq!(Duration::from_secs(2940), db.total_watch_time());
+    }
+
+    #[test]
+    fn total_watch_time_estimated() {
+        let mut db = empty_db();
+        let mut show = watched_anime(3);
+        show.set_duration(Episode::from((1, 1)), Duration::from_secs(600))
+            .unwrap();
+        db.anime_map.insert("Show".to_string(), show);
+        db.anime_map.insert(
+            "Unwatched".to_string(),
+            anime("/nonexistent/Unwatched", Vec::new()),
+        );
+        assert_eq!(
+            Duration::from_secs(600) + DEFAULT_EPISODE_LENGTH * 2,
+            db.total_watch_time()
+        );
+        assert_eq!(
+            Duration::from_secs(600 + 2 * 1800),
+            db.total_watch_time_with(Duration::from_secs(1800))
+        );
+    }
+
+    #[test]
+    fn clone_progress_between_episode_lists() {
+        let episodes = |numbers: &[u32]| {
+            numbers
+                .iter()
+                .map(|&e| (Episode::from((1, e)), vec![format!("Show - {e:02}.mkv")]))
+                .collect::<EpisodeMap>()
+        };
+        let mut source = anime("/nonexistent/Source", episodes(&[1, 2, 3, 4]));
+        source.update_watched(Episode::from((1, 3))).unwrap();
+        source.set_score(Some(8));
+        source.set_status(WatchStatus::Watching);
+
+        let mut renamed = anime("/nonexistent/Renamed", episodes(&[1, 2, 3]));
+        renamed.clone_progress_from(&source);
+        assert_eq!(Episode::from((1, 3)), renamed.current_episode());
+        assert_eq!(source.last_watched, renamed.last_watched);
+        assert_eq!(Some(8), renamed.score());
+        assert_eq!(WatchStatus::Watching, renamed.status());
+        assert!(renamed.is_watched(&Episode::from((1, 3))));
+        assert_eq!(3, renamed.episode_count());
+        assert!(renamed.scan_warnings().is_empty());
+
+        let mut shorter = anime("/nonexistent/Shorter", episodes(&[1, 2]));
+        shorter.clone_progress_from(&source);
+        assert_eq!(Episode::from((1, 2)), shorter.current_episode());
+        assert_eq!(
+            vec![ScanWarning::CurrentEpisodeMissing {
+                episode: Episode::from((1, 3)),
+                moved_to: Some(Episode::from((1, 2))),
+            }],
+            shorter.scan_warnings()
+        );
+    }
+
+    #[test]
+    fn min_episodes_excludes_stray_videos() {
+        let root = fixture(
+            "min-episodes",
+            &[
+                "Show/Show - 01.mkv",
+                "Show/Show - 02.mkv",
+                "Trailer/Trailer.mkv",
+            ],
+        );
+        fs::create_dir(root.join("Empty")).unwrap();
+        let mut db = empty_db();
+        assert_eq!(0, db.scan_config().min_episodes);
+        db.update(vec![root.to_str().unwrap()]);
+        assert_eq!(vec!["Empty", "Show", "Trailer"], db.anime_names());
+
+        let mut db = empty_db();
+        db.set_scan_config(ScanConfig {
+            min_episodes: 2,
+            ..Default::default()
+        });
+        db.update(vec![root.to_str().unwrap()]);
+        assert_eq!(vec!["Show"], db.anime_names());
+    }
+
+    #[test]
+    fn skip_anime_in_progress() {
+        let root = fixture(
+            "in-progress",
+            &[
+                "Done/Done - 01.mkv",
+                "Partial/Partial - 01.mkv",
+                "Partial/Partial - 02.mkv.part",
+            ],
+        );
+        let mut db = empty_db();
+        db.config.settle_secs = Some(3600);
+        db.update(vec![root.to_str().unwrap()]);
+        assert!(db.anime_names().is_empty());
+
+        let mut db = empty_db();
+        db.config.settle_secs = Some(0);
+        db.update(vec![root.to_str().unwrap()]);
+        assert_eq!(vec!["Done"], db.anime_names());
+
+        fs::remove_file(root.join("Partial/Partial - 02.mkv.part")).unwrap();
+        File::create(root.join("Partial/Partial - 02.mkv")).unwrap();
+        db.update(vec![root.to_str().unwrap()]);
+        assert_eq!(vec!["Done", "Partial"], db.anime_names());
+        assert_eq!(2, db.anime("Partial").unwrap().episode_count());
+    }
+
+    #[test]
+    fn stale_without_mtime() {
+        let unsupported = || Err(std::io::Error::from(std::io::ErrorKind::Unsupported));
+        assert!(is_stale(u64::MAX, unsupported()));
+        assert!(is_stale(10, Ok(20)));
+        assert!(!is_stale(20, Ok(20)));
+        assert!(dir_modified_time("/nonexistent/anime").is_err());
+    }
+
+    #[test]
+    fn unconfigured_lists_untouched_anime() {
+        let mut db = empty_db();
+        for name in ["Rated", "Untouched", "Watched", "Dropped", "New"] {
+            db.anime_map.insert(
+                name.to_string(),
+                anime(format!("/nonexistent/{name}"), Vec::new()),
+            );
+        }
+        db.get_anime("Rated").unwrap().set_score(Some(7));
+        db.get_anime("Watched").unwrap().last_watched = 100;
+        db.get_anime("Dropped")
+            .unwrap()
+            .set_status(WatchStatus::Dropped);
+        assert_eq!(vec!["New", "Untouched"], db.unconfigured());
+    }
+
+    #[test]
+    fn normalized_keys_collide() {
+        let root = fixture(
+            "normalize-keys",
+            &["Show/Show - 01.mkv", "SHOW/SHOW - 02.mkv"],
+        );
+        let mut db = empty_db();
+        db.update(vec![root.to_str().unwrap()]);
+        assert_eq!(vec!["SHOW", "Show"], db.anime_names());
+
+        let mut db = empty_db();
+        db.set_scan_config(ScanConfig {
+            normalize_keys: true,
+            ..ScanConfig::default()
+        });
+        db.update(vec![root.to_str().unwrap()]);
+        assert_eq!(vec!["show"], db.anime_names());
+        assert!(db.anime("SHOW").is_some());
+        assert!(db.get_anime("Show").is_some());
+    }
+
+    #[test]
+    fn episode_indices() {
+        let ova = Episode::Special {
+            filename: "Show OVA.mkv".to_string(),
+        };
+        let episodes = [(1, 1), (1, 2), (2, 1)]
+            .into_iter()
+            .map(Episode::from)
+            .chain([ova.clone()])
+            .map(|ep| (ep, Vec::new()))
+            .collect();
+        let mut show = anime("Show", episodes);
+        show.anchor_special("Show OVA.mkv", Episode::from((1, 1)))
+            .unwrap();
+        show.update_watched(Episode::from((1, 2))).unwrap();
+
+        assert_eq!(4, show.flat_episodes().len());
+        let index = show.episode_index(&show.current_episode()).unwrap();
+        assert_eq!(2, index);
+        assert_eq!(Some(&Episode::from((1, 2))), show.episode_at(index));
+        assert_eq!(Some(&ova), show.episode_at(1));
+        assert_eq!(None, show.episode_at(4));
+        assert_eq!(None, show.episode_index(&Episode::from((3, 1))));
+    }
+
+    #[test]
+    #[cfg(feature = "atime")]
+    fn backfill_last_watched_from_atime() {
+        use std::fs::FileTimes;
+        use std::time::Duration;
+
+        let root = fixture(
+            "backfill-atime",
+            &[
+                "Show/Show - 01.mkv",
+                "Show/Show - 02.mkv",
+                "Show/Show - 03.mkv",
+                "Started/Started - 01.mkv",
+            ],
+        );
+        let mut db = empty_db();
+        db.update(vec![root.to_str().unwrap()]);
+        db.update_watched("Started", Episode::from((1, 1))).unwrap();
+        let started = db.anime("Started").unwrap().last_watched;
+
+        let set_accessed = |file: &str, secs: u64| {
+            let time = SystemTime::UNIX_EPOCH + Duration::from_secs(secs);
+            File::options()
+                .write(true)
+                .open(root.join(file))
+                .unwrap()
+                .set_times(FileTimes::new().set_accessed(time))
+                .unwrap();
+        };
+        set_accessed("Show/Show - 01.mkv", 1_000_000);
+        set_accessed("Show/Show - 02.mkv", 3_000_000);
+        set_accessed("Show/Show - 03.mkv", 2_000_000);
+        set_accessed("Started/Started - 01.mkv", 4_000_000);
+
+        assert_eq!(1, db.backfill_from_atime());
+        let show = db.anime("Show").unwrap();
+        assert_eq!(3_000_000, show.last_watched);
+        assert_eq!(Some(Episode::from((1, 2))), show.last_finished());
+        assert_eq!(started, db.anime("Started").unwrap().last_watched);
+    }
+
+    #[test]
+    fn watch_outcomes() {
+        let episodes = [(1, 1), (1, 2), (1, 3)]
+            .into_iter()
+            .map(|ep| (Episode::from(ep), Vec::new()))
+            .collect();
+        let mut show = anime("Show", episodes);
+        let mut watch = |ep| show.update_watched(Episode::from(ep)).unwrap();
+        assert_eq!(WatchOutcome::Advanced, watch((1, 1)));
+        assert_eq!(WatchOutcome::SameEpisode, watch((1, 1)));
+        assert_eq!(WatchOutcome::Advanced, watch((1, 3)));
+        assert_eq!(WatchOutcome::WentBackward, watch((1, 2)));
+    }
+
+    #[test]
+    fn multipart_archives() {
+        let root = fixture(
+            "archives",
+            &[
+                "Show/Show - 01.mkv",
+                "Show/Show - 02.r01",
+                "Show/Show - 02.rar",
+                "Show/Show - 02.r00",
+                "Show/Show.S01E03.rar",
+                "Show/notes.txt",
+            ],
+        );
+        let show = Anime::from_path(root.join("Show"), 0);
+        assert_eq!(1, show.episodes().len());
+        assert_eq!(
+            &vec![
+                (
+                    Episode::from((1, 2)),
+                    vec![
+                        "Show - 02.rar".to_string(),
+                        "Show - 02.r00".to_string(),
+                        "Show - 02.r01".to_string(),
+                    ]
+                ),
+                (Episode::from((1, 3)), vec!["Show.S01E03.rar".to_string()])
+            ],
+            show.archived_episodes()
+        );
+        assert!(show.unparsed().is_empty());
+    }
+
+    #[test]
+    fn archive_releases_and_rar5_volumes() {
+        let root = fixture(
+            "archive-sets",
+            &[
+                "Show/Show - 01.part2.rar",
+                "Show/Show - 01.part1.rar",
+                "Show/Show - 01.part10.rar",
+                "Show/Show - 02 [720p].rar",
+                "Show/Show - 02 [720p].r00",
+                "Show/Show - 02 [1080p].r00",
+                "Show/Show - 02 [1080p].rar",
+            ],
+        );
+        let show = Anime::from_path(root.join("Show"), 0);
+        assert_eq!(
+            &vec![
+                (
+                    Episode::from((1, 1)),
+                    vec![
+                        "Show - 01.part1.rar".to_string(),
+                        "Show - 01.part2.rar".to_string(),
+                        "Show - 01.part10.rar".to_string(),
+                    ]
+                ),
+                (
+                    Episode::from((1, 2)),
+                    vec![
+                        "Show - 02 [1080p].rar".to_string(),
+                        "Show - 02 [1080p].r00".to_string(),
+                        "Show - 02 [720p].rar".to_string(),
+                        "Show - 02 [720p].r00".to_string(),
+                    ]
+                ),
+            ],
+            show.archived_episodes()
+        );
+    }
+
+    #[test]
+    fn rescan_stale_anime() {
+        let root = fixture("rescan-stale", &["Old/Old - 01.mkv", "New/New - 01.mkv"]);
+        let mut db = empty_db();
+        db.update(vec![root.to_str().unwrap()]);
+        db.get_anime("Old").unwrap().last_updated = 0;
+        File::create(root.join("Old/Old - 02.mkv")).unwrap();
+        File::create(root.join("New/New - 02.mkv")).unwrap();
+
+        assert_eq!(1, db.rescan_stale(7 * 24 * 60 * 60));
+        assert_eq!(2, db.anime("Old").unwrap().episodes().len());
+        assert_eq!(1, db.anime("New").unwrap().episodes().len());
+        assert_eq!(0, db.rescan_stale(7 * 24 * 60 * 60));
+    }
+
+    #[test]
+    fn export_anime_json() {
+        let mut db = empty_db();
+        let episodes = vec![
+            ((1, 1).into(), vec!["Show - 01.mkv".to_string()]),
+            ((1, 2).into(), vec!["Show - 02.mkv".to_string()]),
+        ];
+        db.anime_map
+            .insert("Show".to_string(), anime("/anime/Show", episodes));
+        db.update_watched("Show", Episode::from((1, 2))).unwrap();
+        assert_eq!(None, db.export_anime("Other"));
+
+        let json = db.export_anime("Show").unwrap();
+        let show: Anime = serde_json::from_str(&json).unwrap();
+        let original = db.anime("Show").unwrap();
+        assert_eq!(original.episodes(), show.episodes());
+        assert_eq!(original.last_finished(), show.last_finished());
+        assert_eq!(original.last_watched, show.last_watched);
+        assert!(show.is_watched(&Episode::from((1, 2))));
+        assert_eq!(original.resolve_path(""), show.resolve_path(""));
+    }
+
+    #[test]
+    fn report_progress_threshold() {
+        let episodes = [(1, 1), (1, 2)]
+            .into_iter()
+            .map(|ep| (Episode::from(ep), Vec::new()))
+            .collect();
+        let mut show = anime("Show", episodes);
+        let ep = Episode::from((1, 1));
+        assert_eq!(
+            None,
+            show.report_progress(ep.clone(), 1295.0, 1440.0, 0.9)
+                .unwrap()
+        );
+        assert_eq!(None, show.last_finished());
+        assert_eq!(
+            None,
+            show.report_progress(ep.clone(), 0.0, 0.0, 0.9).unwrap()
+        );
+        assert_eq!(
+            Some(WatchOutcome::Advanced),
+            show.report_progress(ep.clone(), 1296.0, 1440.0, 0.9)
+                .unwrap()
+        );
+        assert_eq!(Some(ep.clone()), show.last_finished());
+        assert_eq!(
+            Some(WatchOutcome::SameEpisode),
+            show.report_progress(ep, 1440.0, 1440.0, 0.9).unwrap()
+        );
+        assert!(show
+            .report_progress(Episode::from((2, 1)), 1440.0, 1440.0, 0.9)
+            .is_err());
+    }
+
+    #[test]
+    fn suspicious_groupings() {
+        let root = fixture(
+            "suspicious-groupings",
+            &[
+                "Show/Show - 01.mkv",
+                "Show/[Group] Show - 01 [1080p].mkv",
+                "Show/Show - 02.mkv",
+                "Show/Extras/Unrelated Movie - 02.mkv",
+            ],
+        );
+        let show = Anime::from_path(root.join("Show"), 0);
+        let suspicious = show.suspicious_groupings();
+        assert_eq!(1, suspicious.len());
+        assert_eq!(&Episode::from((1, 2)), suspicious[0].0);
+        assert_eq!(2, suspicious[0].1.len());
+    }
+
+    #[test]
+    fn fake_clock_orders_watches() {
+        let mut db = empty_db();
+        for name in ["A", "B"] {
+            let episodes = vec![(Episode::from((1, 1)), Vec::new())];
+            db.anime_map.insert(name.to_string(), anime(name, episodes));
+        }
+        let now = Arc::new(AtomicU64::new(100));
+        let fake = {
+            let now = now.clone();
+            Clock::new(move || now.load(Ordering::Relaxed))
+        };
+        let names = |db: &mut Database| {
+            db.animes()
+                .unwrap()
+                .iter()
+                .map(|(name, _)| name.to_string())
+                .collect::<Vec<_>>()
+        };
+
+        db.set_clock(fake.clone());
+        db.update_watched("A", Episode::from((1, 1))).unwrap();
+        now.store(200, Ordering::Relaxed);
+        db.update_watched("B", Episode::from((1, 1))).unwrap();
+        assert_eq!(100, db.anime("A").unwrap().last_watched);
+        assert_eq!(200, db.anime("B").unwrap().last_watched);
+        assert_eq!(vec!["B", "A"], names(&mut db));
+
+        // The clock jumps backward between watches.
+        db.set_clock(fake.monotonic());
+        now.store(300, Ordering::Relaxed);
+        db.update_watched("A", Episode::from((1, 1))).unwrap();
+        now.store(250, Ordering::Relaxed);
+        db.update_watched("B", Episode::from((1, 1))).unwrap();
+        assert_eq!(301, db.anime("B").unwrap().last_watched);
+        assert_eq!(vec!["B", "A"], names(&mut db));
+    }
+
+    #[test]
+    fn release_group_counts() {
+        let mut db = empty_db();
+        for (name, files) in [
+            ("A", vec!["[SubsA] A - 01.mkv", "[SubsA] A - 02.mkv"]),
+            ("B", vec!["[SubsA] B - 01.mkv", "[SubsB] B - 02.mkv"]),
+            ("C", vec!["C - 01.mkv"]),
+        ] {
+            let episodes = files
+                .into_iter()
+                .map(|file| (file.parse().unwrap(), vec![file.to_string()]))
+                .collect();
+            db.anime_map.insert(name.to_string(), anime(name, episodes));
+        }
+        let expected = BTreeMap::from([("SubsA".to_string(), 2), ("SubsB".to_string(), 1)]);
+        assert_eq!(expected, db.release_groups());
+    }
+
+    #[test]
+    fn plan_update_diff() {
+        let root = fixture("plan-update", &["Show/Show - 01.mkv", "Show/Show - 02.mkv"]);
+        let mut show = Anime::from_path(root.join("Show"), 0);
+        assert!(show.plan_update().is_empty());
+
+        fs::remove_file(root.join("Show/Show - 01.mkv")).unwrap();
+        File::create(root.join("Show/Show - 03.mkv")).unwrap();
+        let diff = show.plan_update();
+        assert_eq!(
+            EpisodeMapDiff {
+                added: vec![(Episode::from((1, 3)), "Show - 03.mkv".to_string())],
+                removed: vec![(Episode::from((1, 1)), "Show - 01.mkv".to_string())],
+            },
+            diff
+        );
+        assert_eq!(2, show.episodes().len());
+        assert_eq!(Episode::from((1, 1)), show.episodes()[0].0);
+
+        show.update_episodes();
+        assert!(show.plan_update().is_empty());
+    }
+
+    #[test]
+    fn plan_update_with_config() {
+        let root = fixture(
+            "plan-update-config",
+            &["Show/Show - 01.mkv", "Show/Extras/Show - 01 Interview.mkv"],
+        );
+        let config = ScanConfig {
+            extras_dirs: vec!["Extras".to_string()],
+            ..Default::default()
+        };
+        let mut show = Anime::from_path(root.join("Show"), 0);
+        show.update_episodes_with(&config);
+        assert!(show.plan_update_with(&config).is_empty());
+        assert!(!show.plan_update().is_empty());
+    }
+
+    #[test]
+    fn absolute_numbering_in_season_dirs() {
+        let root = fixture(
+            "absolute-numbering",
+            &[
+                "Show/Season 1/Show - 01.mkv",
+                "Show/Season 1/Show - 02.mkv",
+                "Show/Season 2/Show - 03.mkv",
+                "Show/Season 2/Show - 04.mkv",
+            ],
+        );
+        let numbers = |show: &Anime| {
+            show.episodes()
+                .iter()
+                .map(|(ep, _)| ep.clone())
+                .collect::<Vec<_>>()
+        };
+        let mut show = Anime::from_path(root.join("Show"), 0);
+        assert_eq!(
+            vec![(1, 1), (1, 2), (2, 3), (2, 4)]
+                .into_iter()
+                .map(Episode::from)
+                .collect::<Vec<_>>(),
+            numbers(&show)
+        );
+        assert_eq!(
+            vec![Episode::from((2, 1)), Episode::from((2, 2))],
+            show.missing_episodes()
+        );
+        show.update_watched(Episode::from((1, 2))).unwrap();
+        assert_eq!(Some(Episode::from((2, 3))), show.next_episode().unwrap());
+
+        show.set_absolute_numbering(true);
+        show.update_episodes();
+        assert_eq!(
+            vec![(1, 1), (1, 2), (2, 1), (2, 2)]
+                .into_iter()
+                .map(Episode::from)
+                .collect::<Vec<_>>(),
+            numbers(&show)
+        );
+        assert!(show.missing_episodes().is_empty());
+        assert_eq!(2, show.seasons()[&2].len());
+        assert_eq!(Some(Episode::from((2, 1))), show.next_episode().unwrap());
+    }
+
+    #[test]
+    fn duplicate_paths_across_anime() {
+        let root = fixture(
+            "duplicate-paths",
+            &["Show/Show - 01.mkv", "Show/Show - 02.mkv"],
+        );
+        let mut db = empty_db();
+        let shared = vec![((1, 1).into(), vec!["Show - 01.mkv".to_string()])];
+        db.anime_map
+            .insert("Show".to_string(), Anime::from_path(root.join("Show"), 0));
+        db.anime_map
+            .insert("Copy".to_string(), anime(root.join("Show"), shared));
+
+        let path = root.join("Show/Show - 01.mkv").canonicalize().unwrap();
+        assert_eq!(
+            vec![(
+                o_to_str!(path),
+                vec!["Copy".to_string(), "Show".to_string()]
+            )],
+            db.duplicate_paths()
+        );
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn duplicate_paths_through_symlink() {
+        let root = fixture("duplicate-paths-symlink", &["Show/Show - 01.mkv"]);
+        std::os::unix::fs::symlink(root.join("Show"), root.join("Link")).unwrap();
+        let mut db = empty_db();
+        db.anime_map
+            .insert("Show".to_string(), Anime::from_path(root.join("Show"), 0));
+        db.anime_map
+            .insert("Link".to_string(), Anime::from_path(root.join("Link"), 0));
+
+        let path = o_to_str!(root.join("Show/Show - 01.mkv").canonicalize().unwrap());
+        let anime = vec!["Link".to_string(), "Show".to_string()];
+        assert_eq!(vec![(path.clone(), anime.clone())], db.duplicate_paths());
+        assert_eq!(
+            vec![ValidationIssue::DuplicatePath { path, anime }],
+            db.validate()
+        );
+    }
+
+    #[test]
+    fn scan_cache_skips_unchanged_dirs() {
+        use std::fs::FileTimes;
+        use std::time::Duration;
+
+        let root = fixture("scan-cache", &["Show/Show - 01.mkv", "Show/Show - 02.mkv"]);
+        let cache_path = root.join("scan-cache");
+        let show_dir = root.join("Show");
+        let set_modified = |secs: u64| {
+            let time = SystemTime::UNIX_EPOCH + Duration::from_secs(secs);
+            File::open(&show_dir)
+                .unwrap()
+                .set_times(FileTimes::new().set_modified(time))
+                .unwrap();
+        };
+        set_modified(1_000_000);
+
+        let mut cache = ScanCache::open(&cache_path).unwrap();
+        assert!(cache.is_empty());
+        let mut show = Anime::with_episodes(&show_dir, Vec::new());
+        show.update_episodes_cached(&ScanConfig::default(), &mut cache);
+        assert_eq!(2, show.episodes().len());
+        cache.write(&cache_path).unwrap();
+
+        // The directory looks unchanged, so the deletion is not seen.
+        fs::remove_file(show_dir.join("Show - 02.mkv")).unwrap();
+        set_modified(1_000_000);
+        let mut cache = ScanCache::open(&cache_path).unwrap();
+        assert_eq!(1, cache.len());
+        let mut show = Anime::with_episodes(&show_dir, Vec::new());
+        show.update_episodes_cached(&ScanConfig::default(), &mut cache);
+        assert_eq!(2, show.episodes().len());
+
+        set_modified(2_000_000);
+        show.update_episodes_cached(&ScanConfig::default(), &mut cache);
+        assert_eq!(1, show.episodes().len());
+    }
+
+    #[test]
+    fn per_episode_watch_times() {
+        let episodes = [(1, 1), (1, 2)]
+            .into_iter()
+            .map(|ep| (Episode::from(ep), Vec::new()))
+            .collect();
+        let mut show = anime("Show", episodes);
+        let now = Arc::new(AtomicU64::new(100));
+        show.set_clock({
+            let now = now.clone();
+            Clock::new(move || now.load(Ordering::Relaxed))
+        });
+        show.update_watched(Episode::from((1, 1))).unwrap();
+        now.store(200, Ordering::Relaxed);
+        show.update_watched(Episode::from((1, 2))).unwrap();
+        assert_eq!(Some(100), show.watched_at(&Episode::from((1, 1))));
+        assert_eq!(Some(200), show.watched_at(&Episode::from((1, 2))));
+
+        let mut db = empty_db();
+        db.anime_map.insert("Show".to_string(), show);
+        let mut buf = Vec::new();
+        db.to_writer(&mut buf).unwrap();
+        let db = Database::from_reader(buf.as_slice()).unwrap();
+        let show = db.anime("Show").unwrap();
+        assert_eq!(Some(200), show.watched_at(&Episode::from((1, 2))));
+        let show: Anime = serde_json::from_str(&show.to_json().unwrap()).unwrap();
+        assert_eq!(Some(100), show.watched_at(&Episode::from((1, 1))));
+
+        let mut show = show;
+        show.set_watched(Episode::from((1, 1)), false).unwrap();
+        assert_eq!(None, show.watched_at(&Episode::from((1, 1))));
+    }
+
+    #[test]
+    fn extension_hints() {
+        let root = fixture(
+            "extension-hints",
+            &[
+                "Show/Show - 01.mkv",
+                "Show/Show - ED 02.mkv",
+                "Show/Show - 01.mp4",
+            ],
+        );
+        let mut show = Anime::with_episodes(root.join("Show"), Vec::new());
+        show.update_episodes();
+        let special = |filename: &str| Episode::Special {
+            filename: filename.to_string(),
+        };
+        let episodes = |show: &Anime| {
+            show.episodes()
+                .iter()
+                .map(|(ep, _)| ep.clone())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            vec![special("Show - ED 02.mkv"), Episode::from((1, 1))],
+            episodes(&show)
+        );
+
+        let config = ScanConfig {
+            extension_hints: BTreeMap::from([
+                ("mp4".to_string(), ExtensionHint::Special),
+                ("mkv".to_string(), ExtensionHint::Episode),
+            ]),
+            ..ScanConfig::default()
+        };
+        show.update_episodes_with(&config);
+        assert_eq!(
+            vec![
+                special("Show - 01.mp4"),
+                Episode::from((1, 1)),
+                Episode::from((1, 2))
+            ],
+            episodes(&show)
+        );
+    }
+
+    #[test]
+    fn from_map() {
+        let episodes = vec![(Episode::from((1, 1)), Vec::new())];
+        let mut watched = Anime::with_episodes("/anime/Watched", episodes.clone());
+        watched.update_watched(Episode::from((1, 1))).unwrap();
+        let map = BTreeMap::from([
+            (
+                "New".to_string(),
+                Anime::with_episodes("/anime/New", episodes),
+            ),
+            ("Watched".to_string(), watched),
+        ]);
+        let mut db = Database::from_map(map);
+        assert_eq!(vec!["New", "Watched"], db.anime_names());
+        let names = db
+            .animes()
+            .unwrap()
+            .iter()
+            .map(|(name, _)| name.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(vec!["Watched", "New"], names);
+        assert!(matches!(db.save(), Err(DatabaseError::NoPath)));
+    }
+
+    #[test]
+    fn specials_after_finale() {
+        let special = |filename: &str| Episode::Special {
+            filename: filename.to_string(),
+        };
+        let episodes = [(1, 1), (1, 2)]
+            .into_iter()
+            .map(Episode::from)
+            .chain([special("Show - OVA 1.mkv"), special("Show - OVA 2.mkv")])
+            .map(|ep| (ep, Vec::new()))
+            .collect();
+        let mut show = anime("Show", episodes);
+        show.update_watched(Episode::from((1, 2))).unwrap();
+        assert_eq!(None, show.next_episode().unwrap());
+
+        show.set_specials_after_finale(true);
+        assert_eq!(
+            Some(special("Show - OVA 1.mkv")),
+            show.next_episode().unwrap()
+        );
+        show.update_watched(special("Show - OVA 1.mkv")).unwrap();
+        assert_eq!(
+            Some(special("Show - OVA 2.mkv")),
+            show.next_episode().unwrap()
+        );
+        show.update_watched(special("Show - OVA 2.mkv")).unwrap();
+        assert_eq!(None, show.next_episode().unwrap());
+
+        show.update_watched(Episode::from((1, 1))).unwrap();
+        assert_eq!(Some(Episode::from((1, 2))), show.next_episode().unwrap());
+    }
 }
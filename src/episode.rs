@@ -3,17 +3,54 @@ use std::{fmt::Display, path::Path, str::FromStr};
 use regex::Regex;
 use serde::{Serialize, Deserialize};
 use thiserror::Error;
+
+/// Bracketed tags such as `[Group]` or `(1080p)`.
+const BRACKETS: &str = r#"\[[^\]]*\]|\([^)]*\)"#;
+
 lazy_static::lazy_static! {
-    static ref REG_EPS: Regex = Regex::new(r#"(?:(?:^|S|s)(?P<s>\d{2}))?(?: )?(?:_|x|E|e|EP|ep| )(?P<e>\d{1,2})(?:.bits|_| |-|\.|v|$)"#).unwrap();
-    static ref REG_PARSE_OUT: Regex = Regex::new(r#"(x256|x265|\d{4}|\d{3})|10.bits"#).unwrap();
+    static ref REG_EPS: Regex = Regex::new(r#"(?:(?:^|S|s)(?P<s>\d{2}))?(?: )?(?:_|x|E|e|EP|ep| )(?P<e>\d{1,2})(?P<part>[a-d])?(?:_| |-|\.|v|$)"#).unwrap();
+    static ref REG_EPS_DASH: Regex = Regex::new(r#"\s-\s(?:[Ss](?P<s>\d{2})[Ee])?(?P<e>\d{1,2})(?P<part>[a-d])?(?:v\d)?(?:\s|\.|\[|\(|$)"#).unwrap();
+    static ref REG_EPS_BRACKET: Regex = Regex::new(r#"\[(?P<e>\d{1,2})(?P<part>[a-d])?(?:v\d)?\]"#).unwrap();
+    static ref REG_PARSE_OUT: Regex = Regex::new(r#"((?i:[xh]\.?26[45])|\d{4}|\d{3})|(?i:\bavc\b|\bhevc\b|hi10p?|10[.\-_ ]?bits?|\bseason ?\d{1,2}\b)"#).unwrap();
+    static ref REG_DATE: Regex = Regex::new(r#"(?:^|\D)(?P<y>(?:19|20)\d{2})[.\-_ ](?P<m>0[1-9]|1[0-2])[.\-_ ](?P<d>0[1-9]|[12]\d|3[01])(?:\D|$)"#).unwrap();
+    static ref REG_CAPTURE: Regex = Regex::new(r#"(?:^|\D)(?P<y>(?:19|20)\d{2})(?P<m>0[1-9]|1[0-2])(?P<d>0[1-9]|[12]\d|3[01])[_\-T ]?(?P<h>[01]\d|2[0-3])(?P<min>[0-5]\d)(?:\D|$)"#).unwrap();
+    static ref REG_SEASON_WORD: Regex = Regex::new(r#"(?i)\bseason ?(?P<s>\d{1,2})\b"#).unwrap();
+    static ref REG_SEASON_DIR: Regex = Regex::new(r#"(?i)\b(?:season ?|s)(?P<s>\d{1,2})\b"#).unwrap();
+    static ref REG_PART: Regex = Regex::new(r#"(?i)\bpart ?(?P<n>\d{1,2})\b"#).unwrap();
+    static ref REG_SEASON_ORDINAL: Regex = Regex::new(r#"(?i)\b(?P<s>\d{1,2})(?:st|nd|rd|th) season\b"#).unwrap();
+    static ref REG_BRACKETS: Regex = Regex::new(BRACKETS).unwrap();
+    static ref REG_TITLE: Regex = Regex::new(r#"(?:\s-\s|\b[Ss]\d{2}[Ee])\d{1,3}[a-d]?(?:v\d)?\s-\s(?P<t>[^\[(]*?)\s*(?:\[|\(|\.[[:alnum:]]+$|$)"#).unwrap();
+    static ref REG_ROMAN: Regex = Regex::new(r#"\s(?P<r>II|III|IV)\s*$"#).unwrap();
+    static ref REG_QUALITY: Regex = Regex::new(r#"(?i)\b(?:\d{3,4}x(?P<h>\d{3,4})|(?P<p>\d{3,4})[pi]|(?P<k>4k))\b"#).unwrap();
+    static ref REG_GROUP: Regex = Regex::new(r#"^\s*\[(?P<g>[^\]]+)\]"#).unwrap();
+    static ref REG_TAGS: Regex = Regex::new(&format!(r#"{BRACKETS}|\.[[:alnum:]]+$"#)).unwrap();
     static ref REG_SPECIAL: Regex =
-    Regex::new(r#".*OVA.*\.|NCED.*? |NCOP.*? |(-|_| )(ED|OP|SP|no-credit_opening|no-credit_ending).*?(-|_| )"#).unwrap();
+    Regex::new(r#"OVA.*\.|NCED.*? |NCOP.*? |(-|_| )(ED|OP|SP|no-credit_opening|no-credit_ending).*?(-|_| )"#).unwrap();
 }
 
-#[derive(Debug, PartialEq, Ord, Eq, Clone, Deserialize, Serialize)]
+#[derive(Debug, PartialEq, Eq, Clone, Deserialize, Serialize)]
 pub enum Episode {
-    Numbered { season: u32, episode: u32 },
-    Special { filename: String },
+    Numbered {
+        season: u32,
+        episode: u32,
+    },
+    Special {
+        filename: String,
+    },
+    /// Episode identified by its air date, as used by daily shows.
+    Dated {
+        year: u16,
+        month: u8,
+        day: u8,
+    },
+    /// `.ts` TV capture identified by its recording time, e.g. `20230101_1830.ts`.
+    Captured {
+        year: u16,
+        month: u8,
+        day: u8,
+        hour: u8,
+        minute: u8,
+    },
 }
 
 impl Display for Episode {
@@ -21,6 +58,14 @@ impl Display for Episode {
         match self {
             Self::Numbered { season, episode } => write!(f, "S{season:02} E{episode:02}"),
             Self::Special { filename } => filename.fmt(f),
+            Self::Dated { year, month, day } => write!(f, "{year:04}-{month:02}-{day:02}"),
+            Self::Captured {
+                year,
+                month,
+                day,
+                hour,
+                minute,
+            } => write!(f, "{year:04}-{month:02}-{day:02} {hour:02}:{minute:02}"),
         }
     }
 }
@@ -31,8 +76,10 @@ impl From<(u32, u32)> for Episode {
     }
 }
 
-impl PartialOrd for Episode {
-    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+/// Specials sort first, then dated episodes, captures and numbered episodes.
+impl Ord for Episode {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        use std::cmp::Ordering;
         match self {
             Self::Numbered {
                 season: season_a,
@@ -45,28 +92,69 @@ impl PartialOrd for Episode {
                     ..
                 } => {
                     if season_a == season_b {
-                        Some(episode_a.cmp(episode_b))
+                        episode_a.cmp(episode_b)
                     } else {
-                        Some(season_a.cmp(season_b))
+                        season_a.cmp(season_b)
                     }
                 }
-                Self::Special { .. } => Some(std::cmp::Ordering::Greater),
+                Self::Special { .. } | Self::Dated { .. } | Self::Captured { .. } => {
+                    Ordering::Greater
+                }
             },
             Self::Special {
                 filename: filename_a,
                 ..
             } => match other {
-                Self::Numbered { .. } => Some(std::cmp::Ordering::Less),
+                Self::Numbered { .. } | Self::Dated { .. } | Self::Captured { .. } => {
+                    Ordering::Less
+                }
                 Self::Special {
                     filename: filename_b,
                     ..
-                } => Some(filename_a.cmp(filename_b)),
+                } => filename_a.cmp(filename_b),
+            },
+            Self::Dated {
+                year: year_a,
+                month: month_a,
+                day: day_a,
+            } => match other {
+                Self::Numbered { .. } | Self::Captured { .. } => Ordering::Less,
+                Self::Special { .. } => Ordering::Greater,
+                Self::Dated {
+                    year: year_b,
+                    month: month_b,
+                    day: day_b,
+                } => (year_a, month_a, day_a).cmp(&(year_b, month_b, day_b)),
+            },
+            Self::Captured {
+                year: year_a,
+                month: month_a,
+                day: day_a,
+                hour: hour_a,
+                minute: minute_a,
+            } => match other {
+                Self::Numbered { .. } => Ordering::Less,
+                Self::Special { .. } | Self::Dated { .. } => Ordering::Greater,
+                Self::Captured {
+                    year: year_b,
+                    month: month_b,
+                    day: day_b,
+                    hour: hour_b,
+                    minute: minute_b,
+                } => (year_a, month_a, day_a, hour_a, minute_a)
+                    .cmp(&(year_b, month_b, day_b, hour_b, minute_b)),
             },
         }
     }
 }
 
-#[derive(Debug, Error, Eq, PartialEq)]
+impl PartialOrd for Episode {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+#[derive(Debug, Clone, Error, Eq, PartialEq)]
 pub enum EpisodeParseError {
     #[error("Invalid path to episode")]
     InvalidFile,
@@ -79,44 +167,103 @@ pub enum EpisodeParseError {
 impl FromStr for Episode {
     type Err = EpisodeParseError;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        if REG_SPECIAL.is_match(s) {
-            return Ok(Self::Special {
-                filename: s.to_owned(),
-            });
-        }
+        let substituted = REG_PARSE_OUT.replace_all(s, "#");
+        let (_, episode) = apply_rules(s, episode_captures(&substituted).as_ref());
+        Ok(episode)
+    }
+}
 
-        match REG_EPS.captures(&REG_PARSE_OUT.replace_all(s, "#")) {
-            Some(caps) => {
-                let season = caps
-                    .name("s")
-                    .map(|a| a.as_str().parse().expect("Capture is integer"))
-                    .unwrap_or(1);
-                let episode = caps
-                    .name("e")
-                    .map(|a| a.as_str().parse().expect("Capture is integer"))
-                    .ok_or_else(|| Self::Err::InvalidFormat(s.to_string()))?;
-                return Ok(Self::Numbered { season, episode });
-            }
-            None => {
-                return Ok(Self::Special {
-                    filename: s.to_string(),
-                })
-            }
+/// Runs the parse rules on `s` in order, with `caps` from `episode_captures`,
+/// returning the rule that matched and the episode.
+///
+/// `Episode::from_str` and `debug_parse` share this so each regex runs at most
+/// once per filename.
+fn apply_rules(s: &str, caps: Option<&regex::Captures>) -> (ParseRule, Episode) {
+    if let Some(caps) = capture_timestamp(s) {
+        let episode = Episode::Captured {
+            year: caps["y"].parse().expect("Capture is integer"),
+            month: caps["m"].parse().expect("Capture is integer"),
+            day: caps["d"].parse().expect("Capture is integer"),
+            hour: caps["h"].parse().expect("Capture is integer"),
+            minute: caps["min"].parse().expect("Capture is integer"),
+        };
+        return (ParseRule::Capture, episode);
+    }
+
+    if REG_SPECIAL.is_match(s) {
+        let episode = Episode::Special {
+            filename: s.to_owned(),
+        };
+        return (ParseRule::Special, episode);
+    }
+
+    if let Some(caps) = REG_DATE.captures(s) {
+        let episode = Episode::Dated {
+            year: caps["y"].parse().expect("Capture is integer"),
+            month: caps["m"].parse().expect("Capture is integer"),
+            day: caps["d"].parse().expect("Capture is integer"),
+        };
+        return (ParseRule::Date, episode);
+    }
+
+    match caps {
+        Some(caps) => {
+            let season = captured_season(s, caps)
+                .map(|a| a.parse().expect("Capture is integer"))
+                .unwrap_or(1);
+            let episode = caps["e"].parse().expect("Capture is integer");
+            (ParseRule::Episode, Episode::Numbered { season, episode })
+        }
+        None => {
+            let episode = Episode::Special {
+                filename: s.to_string(),
+            };
+            (ParseRule::Fallback, episode)
         }
     }
 }
 
+/// Finds the episode number in a filename after `REG_PARSE_OUT` substitution.
+///
+/// A number after a ` - ` separator is preferred, so a sequel number in the
+/// title (`Show 2 - 05`) is not taken as the episode, followed by a number
+/// alone in brackets, as in `[Group][Show][05][1080p]`.
+fn episode_captures(substituted: &str) -> Option<regex::Captures<'_>> {
+    REG_EPS_DASH
+        .captures(substituted)
+        .or_else(|| REG_EPS_BRACKET.captures(substituted))
+        .or_else(|| REG_EPS.captures(substituted))
+}
+
+/// Gets the season of an episode matched by `episode_captures`: the `s` group
+/// next to the episode number, or else a `Season 2` marker in `s`, which
+/// `REG_PARSE_OUT` hides from the episode rules.
+fn captured_season<'a>(s: &'a str, caps: &regex::Captures<'a>) -> Option<&'a str> {
+    caps.name("s")
+        .or_else(|| REG_SEASON_WORD.captures(s)?.name("s"))
+        .map(|s| s.as_str())
+}
+
+/// Matches the recording timestamp of a `.ts` capture. Other files with
+/// long digit runs are left to the usual rules.
+fn capture_timestamp(s: &str) -> Option<regex::Captures<'_>> {
+    let (_, ext) = s.rsplit_once('.')?;
+    if !ext.eq_ignore_ascii_case("ts") {
+        return None;
+    }
+    REG_CAPTURE.captures(s)
+}
+
 impl TryFrom<&Path> for Episode {
     type Error = EpisodeParseError;
 
     fn try_from(path: &Path) -> Result<Self, Self::Error> {
         let filename = path
-                .file_name()
-                .unwrap()
-                .to_str()
-                .unwrap()
-                .to_string();
-        Ok(filename.parse()?)
+            .file_name()
+            .ok_or(EpisodeParseError::InvalidFile)?
+            .to_str()
+            .ok_or(EpisodeParseError::UTF8)?;
+        filename.parse()
     }
 }
 
@@ -124,6 +271,277 @@ impl Episode {
     pub fn from_path(path: impl AsRef<Path>) -> Result<Episode, EpisodeParseError> {
         Episode::try_from(path.as_ref())
     }
+
+    /// Formats the episode for display, showing specials by
+    /// `special_title` rather than their raw filename.
+    pub fn display_name(&self) -> String {
+        match self {
+            Self::Special { filename } => special_title(filename),
+            ep => ep.to_string(),
+        }
+    }
+
+    /// Compares episodes, ignoring group, quality and hash tags of specials.
+    ///
+    /// Used to group versions of the same special, e.g. `OVA 1 [720p]` and
+    /// `OVA 1 [1080p]`.
+    pub fn normalized_eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Special { filename: a }, Self::Special { filename: b }) => {
+                normalize_special(a) == normalize_special(b)
+            }
+            _ => self == other,
+        }
+    }
+
+    /// Gets the air date in (year, month, day) form of a dated episode or
+    /// capture.
+    pub fn air_date(&self) -> Option<(u16, u8, u8)> {
+        match *self {
+            Self::Dated { year, month, day }
+            | Self::Captured {
+                year, month, day, ..
+            } => Some((year, month, day)),
+            _ => None,
+        }
+    }
+}
+
+/// Regex that decided how a filename was parsed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseRule {
+    /// `REG_CAPTURE` matched a `.ts` filename.
+    Capture,
+    /// `REG_SPECIAL` matched the filename.
+    Special,
+    /// `REG_DATE` matched the filename.
+    Date,
+    /// `REG_EPS_DASH`, `REG_EPS_BRACKET` or `REG_EPS` matched the filename
+    /// after `REG_PARSE_OUT` substitution.
+    Episode,
+    /// Nothing matched, so the filename is a special.
+    Fallback,
+}
+
+/// Intermediate results of parsing a filename, for parser bug reports.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseTrace {
+    pub rule: ParseRule,
+    /// Filename after `REG_PARSE_OUT` substitution.
+    pub substituted: String,
+    /// Raw season number, from the `s` capture of the episode regex or a
+    /// `Season N` marker in the filename.
+    pub season: Option<String>,
+    /// Raw `e` capture of the episode regex.
+    pub episode: Option<String>,
+    /// Episode the filename parses as.
+    pub result: Episode,
+}
+
+/// Ranks a filename by its video resolution, e.g. `1080` for `[1080p]` or
+/// `1920x1080`, so higher is better. Unknown resolutions rank 0.
+pub fn quality_rank(filename: &str) -> u32 {
+    REG_QUALITY
+        .captures_iter(filename)
+        .filter_map(|caps| match caps.name("k") {
+            Some(_) => Some(2160),
+            None => caps.name("h").or(caps.name("p"))?.as_str().parse().ok(),
+        })
+        .max()
+        .unwrap_or(0)
+}
+
+/// Filename parser that can try a custom pattern before the built-in rules,
+/// for release groups with unusual naming schemes.
+#[derive(Debug, Clone, Default)]
+pub struct EpisodeParser {
+    pattern: Option<Regex>,
+}
+
+impl EpisodeParser {
+    /// Creates a parser that tries `pattern` first. The pattern must have an
+    /// `e` group for the episode number and may have an `s` group for the
+    /// season.
+    pub fn with_pattern(pattern: &str) -> Result<Self, regex::Error> {
+        Ok(Self {
+            pattern: Some(Regex::new(pattern)?),
+        })
+    }
+
+    pub fn parse(&self, filename: &str) -> Result<Episode, EpisodeParseError> {
+        let custom = self
+            .pattern
+            .as_ref()
+            .and_then(|pattern| pattern.captures(filename))
+            .and_then(|caps| {
+                let number = |name| caps.name(name)?.as_str().parse::<u32>().ok();
+                Some(Episode::Numbered {
+                    season: number("s").unwrap_or(1),
+                    episode: number("e")?,
+                })
+            });
+        match custom {
+            Some(episode) => Ok(episode),
+            None => filename.parse(),
+        }
+    }
+}
+
+/// Parses `filename`, recording which regex matched and what it captured.
+pub fn debug_parse(filename: &str) -> ParseTrace {
+    let substituted = REG_PARSE_OUT.replace_all(filename, "#");
+    let caps = episode_captures(&substituted);
+    let (rule, result) = apply_rules(filename, caps.as_ref());
+    let capture = |name| Some(caps.as_ref()?.name(name)?.as_str().to_owned());
+    ParseTrace {
+        rule,
+        season: caps
+            .as_ref()
+            .and_then(|caps| captured_season(filename, caps))
+            .map(str::to_owned),
+        episode: capture("e"),
+        result,
+        substituted: substituted.to_string(),
+    }
+}
+
+/// Gets a readable title from a special's filename, e.g. `Creditless OP` for
+/// `[Arid] Sound! Euphonium - Creditless OP [D04F5D1D].mkv`.
+///
+/// Bracketed tags, quality tags and the extension are removed, as is the show
+/// title before a ` - ` separator. Falls back to the filename if nothing is
+/// left.
+pub fn special_title(filename: &str) -> String {
+    let name = REG_TAGS.replace_all(filename, " ");
+    let name = REG_QUALITY.replace_all(&name, " ").replace('_', " ");
+    let name = name.split_whitespace().collect::<Vec<_>>().join(" ");
+    let title = match name.split_once(" - ") {
+        Some((_, title)) => title,
+        None => name.as_str(),
+    };
+    match title.trim_matches(|c: char| c == '-' || c.is_whitespace()) {
+        "" => filename.to_string(),
+        title => title.to_string(),
+    }
+}
+
+/// Strips bracketed tags and the extension from a special's filename.
+fn normalize_special(filename: &str) -> String {
+    REG_TAGS
+        .replace_all(filename, " ")
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+/// Gets the season number written alongside the episode number, or as
+/// `Season 2` elsewhere in the filename, if any.
+///
+/// `Episode` defaults to season 1 when this is `None`.
+pub fn explicit_season(s: &str) -> Option<u32> {
+    let substituted = REG_PARSE_OUT.replace_all(s, "#");
+    let caps = episode_captures(&substituted)?;
+    captured_season(s, &caps).map(|s| s.parse().expect("Capture is integer"))
+}
+
+/// Gets the season number from a directory name such as `Season 2`, `S02`,
+/// `2nd Season`, `Part 2` or `Show II`.
+///
+/// Only `II` to `IV` count as Roman numerals, since titles such as
+/// `Mobile Suit Gundam X` end in single-letter ones.
+pub fn season_from_dir(name: &str) -> Option<u32> {
+    let number = |caps: regex::Captures, name: &str| -> u32 {
+        caps[name].parse().expect("Capture is integer")
+    };
+    if let Some(caps) = REG_SEASON_DIR.captures(name) {
+        return Some(number(caps, "s"));
+    }
+    if let Some(caps) = REG_SEASON_ORDINAL.captures(name) {
+        return Some(number(caps, "s"));
+    }
+    if let Some(caps) = REG_PART.captures(name) {
+        return Some(number(caps, "n"));
+    }
+    let name = REG_TAGS.replace_all(name, " ");
+    let caps = REG_ROMAN.captures(&name)?;
+    ["II", "III", "IV"]
+        .iter()
+        .position(|numeral| *numeral == &caps["r"])
+        .map(|i| i as u32 + 2)
+}
+
+/// Gets the part number of an episode split across multiple files.
+///
+/// Recognizes a letter after the episode number (`- 05a`, `- 05b`) and a
+/// `Part N` marker following the episode number (`- 05 - Part 2`).
+pub fn parse_part(s: &str) -> Option<u32> {
+    let s = REG_PARSE_OUT.replace_all(s, "#");
+    let caps = episode_captures(&s)?;
+    if let Some(part) = caps.name("part") {
+        return Some(u32::from(part.as_str().as_bytes()[0] - b'a') + 1);
+    }
+    let rest = &s[caps.get(0).expect("Capture exists").end()..];
+    REG_PART
+        .captures(rest)
+        .map(|caps| caps["n"].parse().expect("Capture is integer"))
+}
+
+/// Lowercases a title and removes bracketed tags and `.`/`_` separators, so
+/// `[Group] Yuru_Yuri` and `yuru yuri` compare equal.
+pub fn clean_title(name: &str) -> String {
+    REG_BRACKETS
+        .replace_all(name, " ")
+        .split(|c: char| c.is_whitespace() || c == '_' || c == '.')
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+/// Gets the release group from the leading tag of a filename, as in
+/// `[Group] Show - 03 [1080p].mkv`.
+pub fn release_group(filename: &str) -> Option<&str> {
+    let group = REG_GROUP.captures(filename)?.name("g")?.as_str().trim();
+    (!group.is_empty()).then_some(group)
+}
+
+/// Gets the cleaned title written before the episode number, as in
+/// `[Group] Show - 03 [1080p].mkv`, for comparing files of one episode.
+///
+/// Filenames without an episode number give their whole cleaned name.
+pub fn base_title(filename: &str) -> String {
+    let stripped = REG_TAGS.replace_all(filename, " ");
+    let substituted = REG_PARSE_OUT.replace_all(&stripped, "#");
+    let end = episode_captures(&substituted)
+        .and_then(|caps| caps.get(0))
+        .map_or(substituted.len(), |m| m.start());
+    clean_title(&substituted[..end])
+}
+
+/// Gets the episode title written after the episode number, as in
+/// `Show - 03 - Title [1080p].mkv` or `Show - S01E03 - Title.mkv`.
+pub fn episode_title(filename: &str) -> Option<&str> {
+    REG_TITLE
+        .captures(filename)?
+        .name("t")
+        .map(|t| t.as_str())
+        .filter(|t| !t.is_empty())
+}
+
+/// Gets an anime's title with tags and season or part markers removed, so
+/// `Show S2`, `Show 2nd Season` and `Show II` all give `Show`.
+pub fn series_title(name: &str) -> String {
+    let name = REG_TAGS.replace_all(name, " ");
+    let name = REG_SEASON_DIR.replace_all(&name, " ");
+    let name = REG_SEASON_ORDINAL.replace_all(&name, " ");
+    let name = REG_PART.replace_all(&name, " ");
+    let name = REG_ROMAN.replace_all(&name, " ");
+    name.split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .trim_end_matches(|c: char| c == '-' || c == ':' || c.is_whitespace())
+        .to_owned()
 }
 
 #[cfg(test)]
@@ -251,4 +669,528 @@ mod tests {
             Episode::from_str(s)
         );
     }
+
+    #[test]
+    fn episode_part_letter() {
+        let a = "[Group] Show - 05a [1080p].mkv";
+        let b = "[Group] Show - 05b [1080p].mkv";
+        assert_eq!(Ok(Episode::from((1, 5))), Episode::from_str(a));
+        assert_eq!(Ok(Episode::from((1, 5))), Episode::from_str(b));
+        assert_eq!(Some(1), parse_part(a));
+        assert_eq!(Some(2), parse_part(b));
+    }
+
+    #[test]
+    fn episode_part_number() {
+        let a = "[Group] Show - 05 - Part 1.mkv";
+        let b = "[Group] Show - 05 - Part 2.mkv";
+        assert_eq!(Ok(Episode::from((1, 5))), Episode::from_str(a));
+        assert_eq!(Ok(Episode::from((1, 5))), Episode::from_str(b));
+        assert_eq!(Some(1), parse_part(a));
+        assert_eq!(Some(2), parse_part(b));
+        assert_eq!(None, parse_part("[Group] Show Part 2 - 05.mkv"));
+        assert_eq!(None, parse_part("[Group] Show - 05.mkv"));
+    }
+
+    #[test]
+    fn episode_air_date() {
+        let a = Episode::from_str("[Group] Daily Show - 2023.04.15 - Guest [720p].mkv").unwrap();
+        let b = Episode::from_str("Daily Show 2023-04-16.mp4").unwrap();
+        assert_eq!(
+            Episode::Dated {
+                year: 2023,
+                month: 4,
+                day: 15,
+            },
+            a
+        );
+        assert_eq!(Some((2023, 4, 15)), a.air_date());
+        assert_eq!(Some((2023, 4, 16)), b.air_date());
+        assert!(a < b);
+    }
+
+    #[test]
+    fn episode_no_air_date() {
+        let episode = Episode::from_str("[sam] Vinland Saga - 24 [BD 1080p FLAC] [6696F95B].mkv");
+        assert_eq!(Ok(None), episode.map(|e| e.air_date()));
+        let episode = Episode::from_str("[Group] Show (2019) - 04 [1080p].mkv");
+        assert_eq!(Ok(Episode::from((1, 4))), episode);
+    }
+
+    #[test]
+    fn episode_normalized_eq() {
+        let a = Episode::from_str("[Group] Show OVA 1 [720p].mkv").unwrap();
+        let b = Episode::from_str("[Other] Show OVA 1 [1080p][ABCD1234].mkv").unwrap();
+        let c = Episode::from_str("[Group] Show OVA 2 [720p].mkv").unwrap();
+        assert_ne!(a, b);
+        assert!(a.normalized_eq(&b));
+        assert!(!a.normalized_eq(&c));
+        assert!(Episode::from((1, 1)).normalized_eq(&Episode::from((1, 1))));
+    }
+
+    #[test]
+    fn debug_parse_trace() {
+        let trace = debug_parse("[Group] Show - S02E05 [1080p].mkv");
+        assert_eq!(
+            ParseTrace {
+                rule: ParseRule::Episode,
+                substituted: "[Group] Show - S02E05 [#p].mkv".to_string(),
+                season: Some("02".to_string()),
+                episode: Some("05".to_string()),
+                result: Episode::from((2, 5)),
+            },
+            trace
+        );
+        let trace = debug_parse("[Arid] Sound! Euphonium - Creditless OP [D04F5D1D].mkv");
+        assert_eq!(ParseRule::Special, trace.rule);
+    }
+
+    #[test]
+    fn season_markers() {
+        assert_eq!(
+            Some(2),
+            explicit_season("[Group] Show - S02E01 [1080p].mkv")
+        );
+        assert_eq!(None, explicit_season("[Group] Show - E02 [1080p].mkv"));
+        assert_eq!(Some(2), explicit_season("Show Season 2 - 05.mkv"));
+        assert_eq!(Some(2), season_from_dir("Season 2"));
+        assert_eq!(Some(3), season_from_dir("Show S03"));
+        assert_eq!(None, season_from_dir("Extras"));
+        assert_eq!(Some(2), season_from_dir("Show 2nd Season"));
+        assert_eq!(Some(3), season_from_dir("Show 3rd Season [1080p]"));
+        assert_eq!(Some(2), season_from_dir("Show Part 2"));
+        assert_eq!(Some(2), season_from_dir("[Group] Show II [BD 1080p]"));
+        assert_eq!(Some(4), season_from_dir("Show IV"));
+        assert_eq!(None, season_from_dir("Show Ix"));
+        assert_eq!(None, season_from_dir("Gundam X"));
+        assert_eq!(None, season_from_dir("Show V"));
+    }
+
+    fn ordering_samples() -> Vec<Episode> {
+        vec![
+            Episode::from((0, 1)),
+            Episode::from((1, 1)),
+            Episode::from((1, 2)),
+            Episode::from((2, 1)),
+            Episode::Special {
+                filename: "NCOP.mkv".to_string(),
+            },
+            Episode::Special {
+                filename: "OVA.mkv".to_string(),
+            },
+            Episode::Dated {
+                year: 2023,
+                month: 1,
+                day: 2,
+            },
+            Episode::Dated {
+                year: 2023,
+                month: 2,
+                day: 1,
+            },
+            Episode::Captured {
+                year: 2023,
+                month: 1,
+                day: 1,
+                hour: 18,
+                minute: 30,
+            },
+        ]
+    }
+
+    #[test]
+    fn episode_ord_antisymmetric() {
+        let samples = ordering_samples();
+        for a in &samples {
+            for b in &samples {
+                assert_eq!(a.cmp(b), b.cmp(a).reverse(), "{a} vs {b}");
+                assert_eq!(Some(a.cmp(b)), a.partial_cmp(b), "{a} vs {b}");
+                assert_eq!(a == b, a.cmp(b).is_eq(), "{a} vs {b}");
+            }
+        }
+    }
+
+    #[test]
+    fn episode_ord_transitive() {
+        let samples = ordering_samples();
+        for a in &samples {
+            for b in &samples {
+                for c in &samples {
+                    if a <= b && b <= c {
+                        assert!(a <= c, "{a} <= {b} <= {c}");
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn series_titles() {
+        assert_eq!("Yuru Yuri", series_title("Yuru Yuri"));
+        assert_eq!("Yuru Yuri", series_title("Yuru Yuri S2"));
+        assert_eq!("Show", series_title("[Group] Show Season 3 [1080p]"));
+        assert_eq!("Show", series_title("Show 2nd Season"));
+        assert_eq!("Show", series_title("Show - Part 2"));
+        assert_eq!("Show", series_title("[Group] Show II [1080p]"));
+        assert_eq!("Mobile Suit Gundam X", series_title("Mobile Suit Gundam X"));
+    }
+
+    #[test]
+    fn ts_captures() {
+        let capture = |year, month, day, hour, minute| Episode::Captured {
+            year,
+            month,
+            day,
+            hour,
+            minute,
+        };
+        assert_eq!(
+            Ok(capture(2023, 1, 1, 18, 30)),
+            "20230101_1830.ts".parse::<Episode>()
+        );
+        assert_eq!(
+            Ok(capture(2023, 1, 8, 18, 30)),
+            "NHK_20230108-1830.ts".parse::<Episode>()
+        );
+        assert_eq!(
+            Ok(capture(2023, 1, 1, 9, 5)),
+            "20230101 0905 [OP].ts".parse::<Episode>()
+        );
+        assert!(capture(2023, 1, 1, 18, 30) < capture(2023, 1, 8, 9, 0));
+        assert_eq!("2023-01-01 18:30", capture(2023, 1, 1, 18, 30).to_string());
+        assert_eq!(ParseRule::Capture, debug_parse("20230101_1830.ts").rule);
+        assert!(!matches!(
+            "20230101_1830.mkv".parse::<Episode>(),
+            Ok(Episode::Captured { .. })
+        ));
+    }
+
+    #[test]
+    fn clean_titles() {
+        assert_eq!("yuru yuri", clean_title("[Group] Yuru_Yuri (2011)"));
+        assert_eq!("yuru yuri", clean_title("Yuru.Yuri"));
+        assert_eq!("yuru yuri s2", clean_title("  YURU YURI  S2"));
+    }
+
+    #[test]
+    fn ten_bit_tokens() {
+        for filename in [
+            "[Group] Show - 03 [1080p Hi10p].mkv",
+            "[Group] Show - 03 [1080p HI10P].mkv",
+            "[Group] Show - 03 (BD 1080p 10bit).mkv",
+            "[Group] Show - 03 [10-bit].mkv",
+            "[Group] Show - 03 [x265 10 bits].mkv",
+            "Show.S01E03.1080p.10.bits.mkv",
+        ] {
+            assert_eq!(Ok(Episode::from((1, 3))), filename.parse(), "{filename}");
+        }
+        assert_eq!(
+            Ok(Episode::from((1, 10))),
+            "[Group] Show - 10 - Title [1080p 10bit].mkv".parse()
+        );
+    }
+
+    #[test]
+    fn episode_titles() {
+        assert_eq!(
+            Some("An Uneventful Good Life"),
+            episode_title("[Group] Show - 03 - An Uneventful Good Life [1080p].mkv")
+        );
+        assert_eq!(
+            Some("Title, Part 2"),
+            episode_title("Show - S01E03 - Title, Part 2.mkv")
+        );
+        assert_eq!(None, episode_title("[Group] Show - 03 [1080p].mkv"));
+        assert_eq!(None, episode_title("Show.S01E03.1080p.mkv"));
+    }
+
+    #[test]
+    fn no_episode_number_is_special() {
+        for filename in [
+            "Show Season 2 Complete.mkv",
+            "[Group] Show S02 [1080p].mkv",
+            "Show - S02 - Extras.mkv",
+        ] {
+            assert_eq!(
+                Ok(Episode::Special {
+                    filename: filename.to_string()
+                }),
+                filename.parse(),
+                "{filename}"
+            );
+        }
+        assert_eq!(Ok(Episode::from((2, 5))), "Show Season 2 - 05.mkv".parse());
+    }
+
+    #[test]
+    fn custom_parser() {
+        let parser = EpisodeParser::with_pattern(r"#(?P<e>\d+)").unwrap();
+        assert_eq!(Ok(Episode::from((1, 7))), parser.parse("Show #7 Title.mkv"));
+        assert_eq!(Ok(Episode::from((1, 2))), parser.parse("Show - 02.mkv"));
+        assert_eq!(
+            "Show - 02.mkv".parse::<Episode>(),
+            EpisodeParser::default().parse("Show - 02.mkv")
+        );
+    }
+
+    #[test]
+    fn quality_ranks() {
+        let ranks = [
+            "[Group] Show - 01 [2160p].mkv",
+            "[Group] Show - 01 [4K HDR].mkv",
+            "[Group] Show - 01 (BD 1920x1080 x265).mkv",
+            "[Group] Show - 01 [1080p].mkv",
+            "[Group] Show - 01 [720p].mkv",
+            "[Group] Show - 01 [480p].mkv",
+            "[Group] Show - 01.mkv",
+        ]
+        .map(quality_rank);
+        assert_eq!([2160, 2160, 1080, 1080, 720, 480, 0], ranks);
+    }
+
+    #[test]
+    fn prefer_number_after_dash() {
+        for (filename, episode) in [
+            ("[Group] Show 2 - 05 [1080p].mkv", (1, 5)),
+            ("[Group] Show 2 - 05v2 [1080p].mkv", (1, 5)),
+            ("Show 3 - 12.mkv", (1, 12)),
+            ("[Group] 86 - 07 [1080p].mkv", (1, 7)),
+            ("[Group] Show 2 - S02E05 [1080p].mkv", (2, 5)),
+        ] {
+            assert_eq!(Ok(Episode::from(episode)), filename.parse(), "{filename}");
+        }
+        assert_eq!(Some(2), parse_part("[Group] Show 2 - 05b [1080p].mkv"));
+    }
+
+    #[test]
+    fn parse_output_pinned() {
+        // Outputs of every rule, pinned so parser optimizations cannot change
+        // results. `None` means the filename is a special.
+        let cases = [
+            (
+                "[sam] Vinland Saga - 24 [BD 1080p FLAC] [6696F95B].mkv",
+                ParseRule::Episode,
+                Some(Episode::from((1, 24))),
+                None,
+                Some("24"),
+            ),
+            (
+                "[Datte13] Yuyushiki - S01E12 - Uneventful Good Life.mkv",
+                ParseRule::Episode,
+                Some(Episode::from((1, 12))),
+                Some("01"),
+                Some("12"),
+            ),
+            (
+                "[Arid] Sound! Euphonium - Creditless OP [D04F5D1D].mkv",
+                ParseRule::Special,
+                None,
+                None,
+                None,
+            ),
+            (
+                "S00 E03",
+                ParseRule::Episode,
+                Some(Episode::from((0, 3))),
+                Some("00"),
+                Some("03"),
+            ),
+            (
+                "[Group] Show - 05a [1080p].mkv",
+                ParseRule::Episode,
+                Some(Episode::from((1, 5))),
+                None,
+                Some("05"),
+            ),
+            (
+                "[Group] Show 2 - 05 [1080p].mkv",
+                ParseRule::Episode,
+                Some(Episode::from((1, 5))),
+                None,
+                Some("05"),
+            ),
+            (
+                "[Group] Show - 10 - Title [1080p 10bit].mkv",
+                ParseRule::Episode,
+                Some(Episode::from((1, 10))),
+                None,
+                Some("10"),
+            ),
+            (
+                "[Group] Show - 03 [x265 10 bits].mkv",
+                ParseRule::Episode,
+                Some(Episode::from((1, 3))),
+                None,
+                Some("03"),
+            ),
+            (
+                "Show.S01E03.1080p.10.bits.mkv",
+                ParseRule::Episode,
+                Some(Episode::from((1, 3))),
+                Some("01"),
+                Some("03"),
+            ),
+            (
+                "Show Season 2 - 07.mkv",
+                ParseRule::Episode,
+                Some(Episode::from((2, 7))),
+                Some("2"),
+                Some("07"),
+            ),
+            (
+                "Show_ep_11_720p.mp4",
+                ParseRule::Episode,
+                Some(Episode::from((1, 11))),
+                None,
+                Some("11"),
+            ),
+            (
+                "Show x12.mkv",
+                ParseRule::Episode,
+                Some(Episode::from((1, 12))),
+                None,
+                Some("12"),
+            ),
+            ("Show - OVA.mkv", ParseRule::Special, None, None, None),
+            (
+                "[Group] Show NCOP 1 [1080p].mkv",
+                ParseRule::Special,
+                None,
+                None,
+                Some("1"),
+            ),
+            ("Show_ED_v2.mkv", ParseRule::Special, None, None, None),
+            (
+                "Daily Show 2023-04-15.mkv",
+                ParseRule::Date,
+                Some(Episode::Dated {
+                    year: 2023,
+                    month: 4,
+                    day: 15,
+                }),
+                None,
+                None,
+            ),
+            (
+                "20230101_1830.ts",
+                ParseRule::Capture,
+                Some(Episode::Captured {
+                    year: 2023,
+                    month: 1,
+                    day: 1,
+                    hour: 18,
+                    minute: 30,
+                }),
+                None,
+                None,
+            ),
+            ("Show 2023.mkv", ParseRule::Fallback, None, None, None),
+            ("Movie.mkv", ParseRule::Fallback, None, None, None),
+            (
+                "[Group] Show - 01v2 [1080p].mkv",
+                ParseRule::Episode,
+                Some(Episode::from((1, 1))),
+                None,
+                Some("01"),
+            ),
+        ];
+        for (filename, rule, episode, season_capture, episode_capture) in cases {
+            let expected = episode.unwrap_or_else(|| Episode::Special {
+                filename: filename.to_string(),
+            });
+            let trace = debug_parse(filename);
+            assert_eq!(rule, trace.rule, "{filename}");
+            assert_eq!(expected.clone(), trace.result, "{filename}");
+            assert_eq!(Ok(expected), filename.parse(), "{filename}");
+            assert_eq!(season_capture.map(String::from), trace.season, "{filename}");
+            assert_eq!(
+                episode_capture.map(String::from),
+                trace.episode,
+                "{filename}"
+            );
+        }
+    }
+
+    #[test]
+    fn codec_tokens() {
+        for codec in ["x264", "x265", "h264", "h265", "H.264", "AVC", "HEVC"] {
+            for (filename, episode) in [
+                (format!("[Group] Show - 05 [1080p {codec}].mkv"), (1, 5)),
+                (format!("Show.S01E05.{codec}.mkv"), (1, 5)),
+                (format!("Show {codec} 07.mkv"), (1, 7)),
+            ] {
+                assert_eq!(Ok(Episode::from(episode)), filename.parse(), "{filename}");
+            }
+            let filename = format!("Show [{codec}].mkv");
+            let trace = debug_parse(&filename);
+            assert_eq!(ParseRule::Fallback, trace.rule, "{filename}");
+        }
+    }
+
+    #[test]
+    fn base_titles() {
+        assert_eq!("show", base_title("[Group] Show - 03 [1080p].mkv"));
+        assert_eq!("show", base_title("Show.S01E03.1080p.x264.mkv"));
+        assert_eq!("other show", base_title("Other_Show.E03.mkv"));
+        assert_eq!("movie", base_title("Movie.mkv"));
+    }
+
+    #[test]
+    fn release_groups() {
+        assert_eq!(
+            Some("Group"),
+            release_group("[Group] Show - 03 [1080p].mkv")
+        );
+        assert_eq!(Some("sam"), release_group("[sam] Vinland Saga - 24.mkv"));
+        assert_eq!(None, release_group("Show - 03 [1080p].mkv"));
+        assert_eq!(None, release_group("[ ] Show - 03.mkv"));
+    }
+
+    #[test]
+    fn bracketed_episode_numbers() {
+        for (filename, episode) in [
+            ("[Group][Show][05][1080p].mkv", (1, 5)),
+            ("[Group][Show][12v2][720p][6696F95B].mkv", (1, 12)),
+            ("[Group][Show 2][03][1080p].mkv", (1, 3)),
+        ] {
+            assert_eq!(Ok(Episode::from(episode)), filename.parse(), "{filename}");
+        }
+        for filename in [
+            "[Group][Show][1080p].mkv",
+            "[Group][Movie][BD 720p][8bit].mkv",
+        ] {
+            assert_eq!(
+                ParseRule::Fallback,
+                debug_parse(filename).rule,
+                "{filename}"
+            );
+        }
+    }
+
+    #[test]
+    fn special_titles() {
+        for (filename, title) in [
+            (
+                "[Arid] Sound! Euphonium - Creditless OP [D04F5D1D].mkv",
+                "Creditless OP",
+            ),
+            ("Show - OVA.mkv", "OVA"),
+            ("[Group] Show NCOP 1 [1080p].mkv", "Show NCOP 1"),
+            ("Show_ED_v2.mkv", "Show ED v2"),
+            (
+                "[Group] Show - OVA 2 - The Beach (BD 1080p).mkv",
+                "OVA 2 - The Beach",
+            ),
+            ("Show - SP 1080p.mkv", "SP"),
+            ("[Group].mkv", "[Group].mkv"),
+        ] {
+            assert_eq!(title, special_title(filename), "{filename}");
+        }
+        let special = Episode::Special {
+            filename: "Show - OVA.mkv".to_string(),
+        };
+        assert_eq!("OVA", special.display_name());
+        assert_eq!("Show - OVA.mkv", special.to_string());
+        assert_eq!("S01 E02", Episode::from((1, 2)).display_name());
+    }
 }
@@ -1,5 +1,7 @@
 pub mod database;
 pub mod episode;
+#[cfg(feature = "reqwest")]
+mod poster;
 
 #[cfg(test)]
 mod tests {
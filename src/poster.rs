@@ -0,0 +1,155 @@
+use crate::database::{Anime, DatabaseError};
+use serde::{Deserialize, Serialize};
+
+const ANILIST_API: &str = "https://graphql.anilist.co";
+
+const COVER_QUERY: &str =
+    "query ($id: Int) { Media(id: $id, type: ANIME) { coverImage { large } } }";
+
+#[derive(Serialize)]
+struct Query {
+    query: &'static str,
+    variables: Variables,
+}
+
+#[derive(Serialize)]
+struct Variables {
+    id: u64,
+}
+
+#[derive(Deserialize)]
+struct Response {
+    data: Data,
+}
+
+#[derive(Deserialize)]
+struct Data {
+    #[serde(rename = "Media")]
+    media: Option<Media>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Media {
+    cover_image: CoverImage,
+}
+
+#[derive(Deserialize)]
+struct CoverImage {
+    large: Option<String>,
+}
+
+impl Anime {
+    /// Downloads the cover art of the anime with `anilist_id` on AniList, such
+    /// as an ID stored with `.set_extra`.
+    pub fn fetch_poster(&self, anilist_id: u64) -> Result<Vec<u8>, DatabaseError> {
+        fetch_poster_from(ANILIST_API, anilist_id)
+    }
+}
+
+/// Looks up the cover URL with the AniList GraphQL API at `api`, then
+/// downloads it.
+fn fetch_poster_from(api: &str, anilist_id: u64) -> Result<Vec<u8>, DatabaseError> {
+    let query = Query {
+        query: COVER_QUERY,
+        variables: Variables { id: anilist_id },
+    };
+    let client = reqwest::blocking::Client::new();
+    let response = client
+        .post(api)
+        .header("Content-Type", "application/json")
+        .header("Accept", "application/json")
+        .body(serde_json::to_string(&query)?)
+        .send()?
+        .error_for_status()?
+        .text()?;
+    let url = serde_json::from_str::<Response>(&response)?
+        .data
+        .media
+        .and_then(|media| media.cover_image.large)
+        .ok_or(DatabaseError::NoPoster(anilist_id))?;
+    let poster = client.get(url).send()?.error_for_status()?.bytes()?;
+    Ok(poster.to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::thread::JoinHandle;
+
+    /// Answers one request per connection with `respond(path, body)`, then
+    /// returns the requested paths.
+    fn mock_server(
+        listener: TcpListener,
+        requests: usize,
+        respond: impl Fn(&str, &str) -> Vec<u8> + Send + 'static,
+    ) -> JoinHandle<Vec<String>> {
+        std::thread::spawn(move || {
+            let mut paths = Vec::new();
+            for _ in 0..requests {
+                let (stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream);
+                let mut request_line = String::new();
+                reader.read_line(&mut request_line).unwrap();
+                let path = request_line.split_whitespace().nth(1).unwrap().to_string();
+                let mut content_length = 0;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if line.trim().is_empty() {
+                        break;
+                    }
+                    if let Some((name, value)) = line.split_once(':') {
+                        if name.eq_ignore_ascii_case("content-length") {
+                            content_length = value.trim().parse().unwrap();
+                        }
+                    }
+                }
+                let mut body = vec![0; content_length];
+                reader.read_exact(&mut body).unwrap();
+                let response = respond(&path, &String::from_utf8(body).unwrap());
+                let mut stream = reader.into_inner();
+                write!(
+                    stream,
+                    "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    response.len()
+                )
+                .unwrap();
+                stream.write_all(&response).unwrap();
+                paths.push(path);
+            }
+            paths
+        })
+    }
+
+    #[test]
+    fn fetch_poster_from_mock_server() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        let cover = format!("{base}/cover.jpg");
+        let server = mock_server(listener, 2, move |path, body| {
+            if path == "/cover.jpg" {
+                return b"\xFF\xD8\xFF\xE0poster".to_vec();
+            }
+            assert!(body.contains(r#""variables":{"id":21}"#));
+            format!(r#"{{"data":{{"Media":{{"coverImage":{{"large":"{cover}"}}}}}}}}"#).into_bytes()
+        });
+
+        let poster = fetch_poster_from(&format!("{base}/graphql"), 21).unwrap();
+        assert_eq!(poster, b"\xFF\xD8\xFF\xE0poster");
+        assert_eq!(server.join().unwrap(), ["/graphql", "/cover.jpg"]);
+    }
+
+    #[test]
+    fn fetch_poster_missing_media() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        let server = mock_server(listener, 1, |_, _| br#"{"data":{"Media":null}}"#.to_vec());
+
+        let err = fetch_poster_from(&base, 7).unwrap_err();
+        assert!(matches!(err, DatabaseError::NoPoster(7)));
+        server.join().unwrap();
+    }
+}